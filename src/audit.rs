@@ -0,0 +1,176 @@
+use solana_program::pubkey::Pubkey;
+
+use crate::pda::find_comment_address;
+use crate::state::{MovieComment, ProgramAccount};
+
+// Read-side consistency checks for clients and indexers. Nothing here runs
+// on chain; the caller supplies a way to fetch an account's data.
+
+// Something wrong with one index of a review's comment sequence
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CommentIssue {
+    // No account at an index below the counter
+    Missing { index: u64, address: Pubkey },
+    // The account at the index doesn't hold a comment
+    NotAComment { index: u64, address: Pubkey },
+    // The comment names a different review's comment anchor
+    WrongReview { index: u64, address: Pubkey },
+    // The stored count isn't the index the comment is seeded by
+    CountMismatch { index: u64, address: Pubkey, stored: u64 },
+    // A comment exists at the counter's value, which the counter should
+    // already have moved past
+    Uncounted { index: u64, address: Pubkey },
+}
+
+// Walks comments 0..counter of the review with this comment anchor, plus the
+// next index to catch a counter that fell behind. `counter` is the value of
+// the review's comment counter and `get_account_data` returns None for an
+// account that doesn't exist.
+pub fn check_comments<F>(
+    program_id: &Pubkey,
+    comment_anchor: &Pubkey,
+    counter: u64,
+    mut get_account_data: F,
+) -> Vec<CommentIssue>
+where
+    F: FnMut(&Pubkey) -> Option<Vec<u8>>,
+{
+    let mut issues = Vec::new();
+
+    for index in 0..counter {
+        let (address, _) = find_comment_address(program_id, comment_anchor, index);
+
+        let data = match get_account_data(&address) {
+            Some(data) => data,
+            None => {
+                issues.push(CommentIssue::Missing { index, address });
+                continue;
+            }
+        };
+
+        let comment = match MovieComment::load(&data) {
+            Ok(comment) => comment,
+            Err(_) => {
+                issues.push(CommentIssue::NotAComment { index, address });
+                continue;
+            }
+        };
+
+        if comment.reviewer != *comment_anchor {
+            issues.push(CommentIssue::WrongReview { index, address });
+        }
+
+        if comment.count != index {
+            issues.push(CommentIssue::CountMismatch { index, address, stored: comment.count });
+        }
+    }
+
+    let (address, _) = find_comment_address(program_id, comment_anchor, counter);
+    if get_account_data(&address).is_some() {
+        issues.push(CommentIssue::Uncounted { index: counter, address });
+    }
+
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use std::collections::HashMap;
+
+    use crate::state::MovieCommentCounter;
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x4d; 32]);
+
+    fn comment_data(comment_anchor: &Pubkey, count: u64) -> Vec<u8> {
+        MovieComment {
+            discriminator: MovieComment::DISCRIMINATOR,
+            is_initialized: true,
+            reviewer: *comment_anchor,
+            commenter: Pubkey::new_unique(),
+            comment: "Agreed".to_string(),
+            count,
+            edit_count: 0,
+            last_edited_slot: 0,
+            is_deleted: false,
+            parent_comment: None,
+            depth: 0,
+        }
+        .try_to_vec()
+        .unwrap()
+    }
+
+    fn comment_address(comment_anchor: &Pubkey, index: u64) -> Pubkey {
+        find_comment_address(&PROGRAM_ID, comment_anchor, index).0
+    }
+
+    // Accounts by address, with a well-formed comment at every index below
+    // `counter`
+    fn bank(comment_anchor: &Pubkey, counter: u64) -> HashMap<Pubkey, Vec<u8>> {
+        (0..counter)
+            .map(|index| (comment_address(comment_anchor, index), comment_data(comment_anchor, index)))
+            .collect()
+    }
+
+    fn check(bank: &HashMap<Pubkey, Vec<u8>>, comment_anchor: &Pubkey, counter: u64) -> Vec<CommentIssue> {
+        check_comments(&PROGRAM_ID, comment_anchor, counter, |address| bank.get(address).cloned())
+    }
+
+    #[test]
+    fn gap_free_sequence_has_no_issues() {
+        let anchor = Pubkey::new_unique();
+        assert_eq!(check(&bank(&anchor, 0), &anchor, 0), vec![]);
+        assert_eq!(check(&bank(&anchor, 4), &anchor, 4), vec![]);
+    }
+
+    #[test]
+    fn every_kind_of_issue_is_reported_at_its_index() {
+        let anchor = Pubkey::new_unique();
+        let other_anchor = Pubkey::new_unique();
+        let mut bank = bank(&anchor, 5);
+
+        // A gap at 1
+        bank.remove(&comment_address(&anchor, 1));
+        // A comment storing the wrong index at 2
+        bank.insert(comment_address(&anchor, 2), comment_data(&anchor, 7));
+        // A comment naming another review at 3
+        bank.insert(comment_address(&anchor, 3), comment_data(&other_anchor, 3));
+        // Something that isn't a comment at 4
+        let counter = MovieCommentCounter {
+            discriminator: MovieCommentCounter::DISCRIMINATOR,
+            is_initialized: true,
+            counter: 5,
+            review: Pubkey::new_unique(),
+        };
+        bank.insert(comment_address(&anchor, 4), counter.try_to_vec().unwrap());
+        // A comment the counter hasn't moved past at 5
+        bank.insert(comment_address(&anchor, 5), comment_data(&anchor, 5));
+
+        assert_eq!(
+            check(&bank, &anchor, 5),
+            vec![
+                CommentIssue::Missing { index: 1, address: comment_address(&anchor, 1) },
+                CommentIssue::CountMismatch { index: 2, address: comment_address(&anchor, 2), stored: 7 },
+                CommentIssue::WrongReview { index: 3, address: comment_address(&anchor, 3) },
+                CommentIssue::NotAComment { index: 4, address: comment_address(&anchor, 4) },
+                CommentIssue::Uncounted { index: 5, address: comment_address(&anchor, 5) },
+            ]
+        );
+    }
+
+    #[test]
+    fn comment_with_several_problems_reports_each() {
+        let anchor = Pubkey::new_unique();
+        let mut bank = bank(&anchor, 1);
+        bank.insert(comment_address(&anchor, 0), comment_data(&Pubkey::new_unique(), 3));
+
+        assert_eq!(
+            check(&bank, &anchor, 1),
+            vec![
+                CommentIssue::WrongReview { index: 0, address: comment_address(&anchor, 0) },
+                CommentIssue::CountMismatch { index: 0, address: comment_address(&anchor, 0), stored: 3 },
+            ]
+        );
+    }
+}
@@ -25,6 +25,58 @@ pub enum ReviewError{
     // New error added
     #[error("Accounts do not match")]
     IncorrectAccountError,
+
+    // Instruction payload decoding, one per field so a bad client is easy to spot
+    #[error("Instruction data has a malformed title")]
+    InvalidTitleData,
+    #[error("Instruction data has a malformed rating")]
+    InvalidRatingData,
+    #[error("Instruction data has a malformed description")]
+    InvalidDescriptionData,
+    #[error("Instruction data has a malformed comment")]
+    InvalidCommentData,
+
+    #[error("Review still has comments attached")]
+    ReviewHasComments,
+
+    #[error("Account discriminator does not match the expected type")]
+    InvalidAccountKind,
+
+    #[error("Instruction data has a malformed movie id")]
+    InvalidMovieIdData,
+    #[error("Instruction data has a malformed year")]
+    InvalidYearData,
+    #[error("Instruction data has a malformed external id")]
+    InvalidExternalIdData,
+    #[error("Movie with this title and year is already registered")]
+    MovieAlreadyRegistered,
+
+    #[error("Comment has been deleted")]
+    CommentDeleted,
+    #[error("Reply is nested deeper than allowed")]
+    ReplyTooDeep,
+    #[error("Parent comment belongs to a different review")]
+    ParentCommentMismatch,
+
+    #[error("Instruction data has a malformed vote")]
+    InvalidVoteData,
+    #[error("Reviewers cannot vote on their own review")]
+    CannotVoteOnOwnReview,
+    #[error("No vote to withdraw")]
+    VoteNotFound,
+
+    #[error("Instruction data has malformed config settings")]
+    InvalidConfigData,
+    #[error("Only the config admin can do this")]
+    NotConfigAdmin,
+    #[error("Reviewers cannot comment on their own review")]
+    SelfCommentRejected,
+
+    #[error("Instruction data has malformed token metadata")]
+    InvalidMetadataData,
+
+    #[error("Only the program's upgrade authority can do this")]
+    NotUpgradeAuthority,
 }
 
 impl From<ReviewError> for ProgramError {
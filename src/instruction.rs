@@ -1,14 +1,38 @@
-use borsh::BorshDeserialize;
-use solana_program::program_error::ProgramError;
+use borsh::{BorshDeserialize, BorshSerialize};
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    program_error::ProgramError,
+    pubkey::Pubkey,
+    system_program::ID as SYSTEM_PROGRAM_ID,
+    sysvar::rent::ID as RENT_PROGRAM_ID,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, ID as ASSOCIATED_TOKEN_PROGRAM_ID,
+};
+use spl_token::ID as TOKEN_PROGRAM_ID;
 
+use crate::error::ReviewError;
+use crate::metadata::TOKEN_METADATA_PROGRAM_ID;
+use crate::state::{RewardLimits, RewardSetting, SelfCommentPolicy};
+use crate::pda::{
+    find_aggregate_address, find_comment_address, find_comment_anchor_address, find_comment_counter_address,
+    find_config_address, find_legacy_review_address, find_metadata_address, find_mint_address,
+    find_mint_authority_address, find_movie_address, find_movie_registry_address, find_movie_title_address,
+    find_program_data_address, find_reply_address, find_reply_counter_address, find_review_address,
+    find_reviewer_address, find_reward_ledger_address, find_vote_address, find_vote_tally_address,
+};
+
+// The wire format is the Borsh encoding of this enum: a one byte tag (the
+// variant's position below, so never reorder them) followed by its fields.
+#[derive(BorshSerialize, BorshDeserialize, Debug, PartialEq)]
 pub enum MovieInstruction {
     AddMovieReview {
-        title: String,
+        movie_id: u64,
         rating: u8,
         description: String,
     },
     UpdateMovieReview {
-        title: String,
+        movie_id: u64,
         rating: u8,
         description: String
     },
@@ -16,52 +40,768 @@ pub enum MovieInstruction {
         comment: String,
     },
     InitializeMint,
+    DeleteMovieReview,
+    RegisterMovie {
+        title: String,
+        year: u16,
+        imdb_id: String,
+    },
+    MigrateReview {
+        movie_id: u64,
+    },
+    UpdateComment {
+        comment: String,
+    },
+    DeleteComment,
+    ReplyToComment {
+        comment: String,
+    },
+    VoteOnReview {
+        helpful: bool,
+    },
+    WithdrawVote,
+    InitializeConfig {
+        review_reward: RewardSetting,
+        comment_reward: RewardSetting,
+        reward_limits: RewardLimits,
+        self_comment_policy: SelfCommentPolicy,
+    },
+    UpdateConfig {
+        admin: Pubkey,
+        review_reward: RewardSetting,
+        comment_reward: RewardSetting,
+        reward_limits: RewardLimits,
+        self_comment_policy: SelfCommentPolicy,
+    },
+    CreateMintMetadata {
+        name: String,
+        symbol: String,
+        uri: String,
+    },
 }
 
 impl MovieInstruction {
+    // Reads the same layout the derived BorshDeserialize does, field by field,
+    // so a bad payload reports which field it failed on instead of a generic error
     pub fn unpack(input: &[u8]) -> Result<Self, ProgramError> {
-        let (&variant, rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
-
-
-        Ok(match variant {
-            0 => {
-                let payload = MovieReviewPayload::try_from_slice(rest).unwrap();
+        let (&variant, mut rest) = input.split_first().ok_or(ProgramError::InvalidInstructionData)?;
+        let rest = &mut rest;
 
-                Self::AddMovieReview {
-                    title: payload.title,
-                    rating: payload.rating,
-                    description: payload.description
-                }
+        let instruction = match variant {
+            0 => Self::AddMovieReview {
+                movie_id: unpack_field(rest, ReviewError::InvalidMovieIdData)?,
+                rating: unpack_field(rest, ReviewError::InvalidRatingData)?,
+                description: unpack_field(rest, ReviewError::InvalidDescriptionData)?,
             },
-            1 => {
-                let payload = MovieReviewPayload::try_from_slice(rest).unwrap();
-
-                Self::UpdateMovieReview {
-                    title: payload.title,
-                    rating: payload.rating,
-                    description: payload.description 
-                }
+            1 => Self::UpdateMovieReview {
+                movie_id: unpack_field(rest, ReviewError::InvalidMovieIdData)?,
+                rating: unpack_field(rest, ReviewError::InvalidRatingData)?,
+                description: unpack_field(rest, ReviewError::InvalidDescriptionData)?,
+            },
+            2 => Self::AddComments {
+                comment: unpack_field(rest, ReviewError::InvalidCommentData)?,
             },
-            2 => {
-                let payload = CommentPayload::try_from_slice(rest).unwrap();
-                
-                Self::AddComments { comment: payload.comment }
-            }
-            // New variant added here
             3 => Self::InitializeMint,
+            4 => Self::DeleteMovieReview,
+            5 => Self::RegisterMovie {
+                title: unpack_field(rest, ReviewError::InvalidTitleData)?,
+                year: unpack_field(rest, ReviewError::InvalidYearData)?,
+                imdb_id: unpack_field(rest, ReviewError::InvalidExternalIdData)?,
+            },
+            6 => Self::MigrateReview {
+                movie_id: unpack_field(rest, ReviewError::InvalidMovieIdData)?,
+            },
+            7 => Self::UpdateComment {
+                comment: unpack_field(rest, ReviewError::InvalidCommentData)?,
+            },
+            8 => Self::DeleteComment,
+            9 => Self::ReplyToComment {
+                comment: unpack_field(rest, ReviewError::InvalidCommentData)?,
+            },
+            10 => Self::VoteOnReview {
+                helpful: unpack_field(rest, ReviewError::InvalidVoteData)?,
+            },
+            11 => Self::WithdrawVote,
+            12 => Self::InitializeConfig {
+                review_reward: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                comment_reward: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                reward_limits: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                self_comment_policy: unpack_field(rest, ReviewError::InvalidConfigData)?,
+            },
+            13 => Self::UpdateConfig {
+                admin: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                review_reward: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                comment_reward: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                reward_limits: unpack_field(rest, ReviewError::InvalidConfigData)?,
+                self_comment_policy: unpack_field(rest, ReviewError::InvalidConfigData)?,
+            },
+            14 => Self::CreateMintMetadata {
+                name: unpack_field(rest, ReviewError::InvalidMetadataData)?,
+                symbol: unpack_field(rest, ReviewError::InvalidMetadataData)?,
+                uri: unpack_field(rest, ReviewError::InvalidMetadataData)?,
+            },
             _ => return Err(ProgramError::InvalidInstructionData)
-        })
+        };
+
+        // Anything left over means the client and program disagree on the layout
+        if !rest.is_empty() {
+            return Err(ProgramError::InvalidInstructionData);
+        }
+
+        Ok(instruction)
+    }
+
+    pub fn pack(&self) -> Vec<u8> {
+        self.try_to_vec().unwrap()
     }
 }
 
-#[derive(BorshDeserialize)]
-struct MovieReviewPayload {
-    title: String,
+fn unpack_field<T: BorshDeserialize>(input: &mut &[u8], error: ReviewError) -> Result<T, ProgramError> {
+    T::deserialize(input).map_err(|_| error.into())
+}
+
+// Client-side builders. The account order of each one mirrors the order the
+// matching function in processor.rs pulls accounts off the iterator.
+//
+// `comment_anchor` is the review's stored comment anchor, which its comment
+// counter, comments and votes are seeded from.
+
+// `review_count` is the initializer's current review count, 0 if they have no
+// reviewer account yet; the new review's comment anchor is derived from it
+pub fn add_movie_review(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    review_count: u64,
+    movie_id: u64,
+    rating: u8,
+    description: String,
+) -> Instruction {
+    let (pda_review, _) = find_review_address(program_id, initializer, movie_id);
+    let (comment_anchor, _) = find_comment_anchor_address(program_id, initializer, review_count);
+    let (pda_counter, _) = find_comment_counter_address(program_id, &comment_anchor);
+    let (pda_aggregate, _) = find_aggregate_address(program_id, movie_id);
+    let (pda_movie, _) = find_movie_address(program_id, movie_id);
+    let (pda_reviewer, _) = find_reviewer_address(program_id, initializer);
+    let (token_mint, _) = find_mint_address(program_id);
+    let (mint_auth, _) = find_mint_authority_address(program_id);
+    let user_ata = get_associated_token_address(initializer, &token_mint);
+    let (pda_config, _) = find_config_address(program_id);
+    let (pda_ledger, _) = find_reward_ledger_address(program_id, initializer);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::AddMovieReview { movie_id, rating, description }.pack(),
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(pda_review, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new(pda_counter, false),
+            AccountMeta::new(pda_aggregate, false),
+            AccountMeta::new_readonly(pda_movie, false),
+            AccountMeta::new(pda_reviewer, false),
+            AccountMeta::new_readonly(pda_config, false),
+            AccountMeta::new(token_mint, false),
+            AccountMeta::new_readonly(mint_auth, false),
+            AccountMeta::new(user_ata, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new(pda_ledger, false),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+        ],
+    )
+}
+
+pub fn update_movie_review(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    movie_id: u64,
+    rating: u8,
+    description: String,
+) -> Instruction {
+    let (pda_review, _) = find_review_address(program_id, initializer, movie_id);
+    let (pda_aggregate, _) = find_aggregate_address(program_id, movie_id);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::UpdateMovieReview { movie_id, rating, description }.pack(),
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(pda_review, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new(pda_aggregate, false),
+        ],
+    )
+}
+
+// Moves the review of `old_movie_id` to the address for `new_movie_id`, the
+// old account's rent is refunded to the reviewer
+pub fn move_movie_review(
+    program_id: &Pubkey,
+    initializer: &Pubkey,
+    old_movie_id: u64,
+    comment_anchor: &Pubkey,
+    new_movie_id: u64,
     rating: u8,
-    description: String
+    description: String,
+) -> Instruction {
+    let (pda_review, _) = find_review_address(program_id, initializer, old_movie_id);
+    let (new_pda_review, _) = find_review_address(program_id, initializer, new_movie_id);
+    let (pda_counter, _) = find_comment_counter_address(program_id, comment_anchor);
+    let (pda_aggregate, _) = find_aggregate_address(program_id, old_movie_id);
+    let (new_pda_aggregate, _) = find_aggregate_address(program_id, new_movie_id);
+    let (new_pda_movie, _) = find_movie_address(program_id, new_movie_id);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::UpdateMovieReview { movie_id: new_movie_id, rating, description }.pack(),
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(pda_review, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new(pda_aggregate, false),
+            AccountMeta::new(new_pda_review, false),
+            AccountMeta::new(pda_counter, false),
+            AccountMeta::new(new_pda_aggregate, false),
+            AccountMeta::new_readonly(new_pda_movie, false),
+        ],
+    )
+}
+
+// Number of accounts in the reward group that ends add_movie_review and
+// add_comment. The config comes before it and stays
+pub const REWARD_ACCOUNTS_LEN: usize = 6;
+
+// Drops the reward group from an add_movie_review or add_comment instruction,
+// so the review or comment is stored without minting anything. The config's
+// self-comment policy still applies
+pub fn without_rewards(mut instruction: Instruction) -> Instruction {
+    let len = instruction.accounts.len().saturating_sub(REWARD_ACCOUNTS_LEN);
+    instruction.accounts.truncate(len);
+    instruction
+}
+
+// `comment_count` is the current value of the review's comment counter,
+// the new comment is created at that index
+pub fn add_comment(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    pda_review: &Pubkey,
+    comment_anchor: &Pubkey,
+    comment_count: u64,
+    comment: String,
+) -> Instruction {
+    let (pda_counter, _) = find_comment_counter_address(program_id, comment_anchor);
+    let (pda_comment, _) = find_comment_address(program_id, comment_anchor, comment_count);
+    let (token_mint, _) = find_mint_address(program_id);
+    let (mint_auth, _) = find_mint_authority_address(program_id);
+    let user_ata = get_associated_token_address(commenter, &token_mint);
+    let (pda_config, _) = find_config_address(program_id);
+    let (pda_ledger, _) = find_reward_ledger_address(program_id, commenter);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::AddComments { comment }.pack(),
+        vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new_readonly(*pda_review, false),
+            AccountMeta::new(pda_counter, false),
+            AccountMeta::new(pda_comment, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(pda_config, false),
+            AccountMeta::new(token_mint, false),
+            AccountMeta::new_readonly(mint_auth, false),
+            AccountMeta::new(user_ata, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new(pda_ledger, false),
+            AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM_ID, false),
+        ],
+    )
+}
+
+pub fn initialize_mint(program_id: &Pubkey, initializer: &Pubkey) -> Instruction {
+    let (token_mint, _) = find_mint_address(program_id);
+    let (mint_auth, _) = find_mint_authority_address(program_id);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::InitializeMint.pack(),
+        vec![
+            AccountMeta::new(*initializer, true),
+            AccountMeta::new(token_mint, false),
+            AccountMeta::new_readonly(mint_auth, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_PROGRAM_ID, false),
+            AccountMeta::new_readonly(RENT_PROGRAM_ID, false),
+        ],
+    )
+}
+
+// Rent from the review, its comment counter and its vote tally is returned to
+// `destination`
+pub fn delete_movie_review(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    movie_id: u64,
+    comment_anchor: &Pubkey,
+    destination: &Pubkey,
+) -> Instruction {
+    let (pda_review, _) = find_review_address(program_id, reviewer, movie_id);
+    let (pda_counter, _) = find_comment_counter_address(program_id, comment_anchor);
+    let (pda_aggregate, _) = find_aggregate_address(program_id, movie_id);
+    let (pda_tally, _) = find_vote_tally_address(program_id, comment_anchor);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::DeleteMovieReview.pack(),
+        vec![
+            AccountMeta::new_readonly(*reviewer, true),
+            AccountMeta::new(pda_review, false),
+            AccountMeta::new(pda_counter, false),
+            AccountMeta::new(*destination, false),
+            AccountMeta::new(pda_aggregate, false),
+            AccountMeta::new(pda_tally, false),
+        ],
+    )
 }
 
-#[derive(BorshDeserialize)]
-struct CommentPayload {
+// Signed by the config admin, who pays for the movie. `movie_id` is the
+// registry's current movie count, which becomes the new movie's id
+pub fn register_movie(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    movie_id: u64,
+    title: String,
+    year: u16,
+    imdb_id: String,
+) -> Instruction {
+    let (pda_config, _) = find_config_address(program_id);
+    let (pda_registry, _) = find_movie_registry_address(program_id);
+    let (pda_movie, _) = find_movie_address(program_id, movie_id);
+    let (pda_title, _) = find_movie_title_address(program_id, &title, year);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::RegisterMovie { title, year, imdb_id }.pack(),
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(pda_config, false),
+            AccountMeta::new(pda_registry, false),
+            AccountMeta::new(pda_movie, false),
+            AccountMeta::new(pda_title, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    )
+}
+
+// Moves a legacy review stored under `title` to the address for `movie_id`.
+// Fails if `title` is too long to have been a legacy seed
+pub fn migrate_review(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    title: &str,
+    movie_id: u64,
+) -> Result<Instruction, ProgramError> {
+    let (legacy_pda_review, _) = find_legacy_review_address(program_id, reviewer, title)?;
+    let (pda_counter, _) = find_comment_counter_address(program_id, &legacy_pda_review);
+    let (pda_review, _) = find_review_address(program_id, reviewer, movie_id);
+    let (pda_aggregate, _) = find_aggregate_address(program_id, movie_id);
+    let (pda_movie, _) = find_movie_address(program_id, movie_id);
+
+    Ok(Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::MigrateReview { movie_id }.pack(),
+        vec![
+            AccountMeta::new(*reviewer, true),
+            AccountMeta::new(legacy_pda_review, false),
+            AccountMeta::new(pda_counter, false),
+            AccountMeta::new(pda_review, false),
+            AccountMeta::new(pda_aggregate, false),
+            AccountMeta::new_readonly(pda_movie, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    ))
+}
+
+pub fn update_comment(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    pda_comment: &Pubkey,
     comment: String,
-}
\ No newline at end of file
+) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::UpdateComment { comment }.pack(),
+        vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new(*pda_comment, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    )
+}
+
+// The comment's text is cleared and the rent it no longer needs is refunded
+// to the commenter
+pub fn delete_comment(program_id: &Pubkey, commenter: &Pubkey, pda_comment: &Pubkey) -> Instruction {
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::DeleteComment.pack(),
+        vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new(*pda_comment, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    )
+}
+
+// `reply_count` is the parent's current reply count, 0 if it has no reply
+// counter yet; the new reply is created at that index
+pub fn reply_to_comment(
+    program_id: &Pubkey,
+    commenter: &Pubkey,
+    pda_review: &Pubkey,
+    parent_comment: &Pubkey,
+    reply_count: u64,
+    comment: String,
+) -> Instruction {
+    let (pda_reply_counter, _) = find_reply_counter_address(program_id, parent_comment);
+    let (pda_reply, _) = find_reply_address(program_id, parent_comment, reply_count);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::ReplyToComment { comment }.pack(),
+        vec![
+            AccountMeta::new(*commenter, true),
+            AccountMeta::new_readonly(*pda_review, false),
+            AccountMeta::new_readonly(*parent_comment, false),
+            AccountMeta::new(pda_reply_counter, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new(pda_reply, false),
+        ],
+    )
+}
+
+// Casts or changes `voter`'s vote on a review
+pub fn vote_on_review(
+    program_id: &Pubkey,
+    voter: &Pubkey,
+    pda_review: &Pubkey,
+    comment_anchor: &Pubkey,
+    helpful: bool,
+) -> Instruction {
+    vote_instruction(
+        program_id,
+        voter,
+        pda_review,
+        comment_anchor,
+        MovieInstruction::VoteOnReview { helpful },
+    )
+}
+
+// The vote account's rent is refunded to `voter`, which works after the
+// review is deleted too
+pub fn withdraw_vote(
+    program_id: &Pubkey,
+    voter: &Pubkey,
+    pda_review: &Pubkey,
+    comment_anchor: &Pubkey,
+) -> Instruction {
+    vote_instruction(program_id, voter, pda_review, comment_anchor, MovieInstruction::WithdrawVote)
+}
+
+fn vote_instruction(
+    program_id: &Pubkey,
+    voter: &Pubkey,
+    pda_review: &Pubkey,
+    comment_anchor: &Pubkey,
+    instruction: MovieInstruction,
+) -> Instruction {
+    let (pda_vote, _) = find_vote_address(program_id, comment_anchor, voter);
+    let (pda_tally, _) = find_vote_tally_address(program_id, comment_anchor);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &instruction.pack(),
+        vec![
+            AccountMeta::new(*voter, true),
+            AccountMeta::new_readonly(*pda_review, false),
+            AccountMeta::new(pda_vote, false),
+            AccountMeta::new(pda_tally, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    )
+}
+
+// `admin` must be the program's upgrade authority and becomes the config's admin
+pub fn initialize_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    review_reward: RewardSetting,
+    comment_reward: RewardSetting,
+    reward_limits: RewardLimits,
+    self_comment_policy: SelfCommentPolicy,
+) -> Instruction {
+    let (pda_config, _) = find_config_address(program_id);
+    let (program_data, _) = find_program_data_address(program_id);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::InitializeConfig {
+            review_reward,
+            comment_reward,
+            reward_limits,
+            self_comment_policy,
+        }
+        .pack(),
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new(pda_config, false),
+            AccountMeta::new_readonly(program_data, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+        ],
+    )
+}
+
+// Signed by the current admin, `new_admin` hands the config over
+pub fn update_config(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    new_admin: Pubkey,
+    review_reward: RewardSetting,
+    comment_reward: RewardSetting,
+    reward_limits: RewardLimits,
+    self_comment_policy: SelfCommentPolicy,
+) -> Instruction {
+    let (pda_config, _) = find_config_address(program_id);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::UpdateConfig {
+            admin: new_admin,
+            review_reward,
+            comment_reward,
+            reward_limits,
+            self_comment_policy,
+        }
+        .pack(),
+        vec![
+            AccountMeta::new_readonly(*admin, true),
+            AccountMeta::new(pda_config, false),
+        ],
+    )
+}
+
+// Signed by the config admin, who also pays for the metadata account
+pub fn create_mint_metadata(
+    program_id: &Pubkey,
+    admin: &Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+) -> Instruction {
+    let (pda_config, _) = find_config_address(program_id);
+    let (token_mint, _) = find_mint_address(program_id);
+    let (mint_auth, _) = find_mint_authority_address(program_id);
+    let (metadata, _) = find_metadata_address(&token_mint);
+
+    Instruction::new_with_bytes(
+        *program_id,
+        &MovieInstruction::CreateMintMetadata { name, symbol, uri }.pack(),
+        vec![
+            AccountMeta::new(*admin, true),
+            AccountMeta::new_readonly(pda_config, false),
+            AccountMeta::new_readonly(token_mint, false),
+            AccountMeta::new(metadata, false),
+            AccountMeta::new_readonly(mint_auth, false),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(RENT_PROGRAM_ID, false),
+            AccountMeta::new_readonly(TOKEN_METADATA_PROGRAM_ID, false),
+        ],
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn all_variants() -> Vec<MovieInstruction> {
+        let reward = RewardSetting { amount: 10_000_000_000, enabled: true };
+        let limits = RewardLimits { cap_per_window: 50, window_slots: 216_000, cooldown_slots: 150 };
+
+        vec![
+            MovieInstruction::AddMovieReview { movie_id: 7, rating: 5, description: "Great".to_string() },
+            MovieInstruction::UpdateMovieReview { movie_id: 7, rating: 3, description: "Fine".to_string() },
+            MovieInstruction::AddComments { comment: "Agreed".to_string() },
+            MovieInstruction::InitializeMint,
+            MovieInstruction::DeleteMovieReview,
+            MovieInstruction::RegisterMovie { title: "Heat".to_string(), year: 1995, imdb_id: "tt0113277".to_string() },
+            MovieInstruction::MigrateReview { movie_id: 7 },
+            MovieInstruction::UpdateComment { comment: "Edited".to_string() },
+            MovieInstruction::DeleteComment,
+            MovieInstruction::ReplyToComment { comment: "Reply".to_string() },
+            MovieInstruction::VoteOnReview { helpful: true },
+            MovieInstruction::WithdrawVote,
+            MovieInstruction::InitializeConfig {
+                review_reward: reward,
+                comment_reward: reward,
+                reward_limits: limits,
+                self_comment_policy: SelfCommentPolicy::ReducedReward { amount: 1 },
+            },
+            MovieInstruction::UpdateConfig {
+                admin: Pubkey::new_unique(),
+                review_reward: reward,
+                comment_reward: RewardSetting { amount: 0, enabled: false },
+                reward_limits: limits,
+                self_comment_policy: SelfCommentPolicy::Reject,
+            },
+            MovieInstruction::CreateMintMetadata {
+                name: "Movie Review Token".to_string(),
+                symbol: "MRT".to_string(),
+                uri: "https://example.com/mrt.json".to_string(),
+            },
+        ]
+    }
+
+    #[test]
+    fn pack_unpack_round_trip() {
+        for (tag, instruction) in all_variants().into_iter().enumerate() {
+            let data = instruction.pack();
+            assert_eq!(data[0] as usize, tag);
+            assert_eq!(MovieInstruction::unpack(&data).unwrap(), instruction);
+        }
+    }
+
+    fn unpack_err(data: &[u8]) -> ProgramError {
+        MovieInstruction::unpack(data).unwrap_err()
+    }
+
+    // A Borsh string with a length prefix of `len` but only `body` behind it
+    fn string_with_len(len: u32, body: &[u8]) -> Vec<u8> {
+        let mut data = len.to_le_bytes().to_vec();
+        data.extend_from_slice(body);
+        data
+    }
+
+    #[test]
+    fn unpack_rejects_empty_input_and_unknown_tags() {
+        assert_eq!(unpack_err(&[]), ProgramError::InvalidInstructionData);
+        for tag in [15u8, 16, 200, 255] {
+            assert_eq!(unpack_err(&[tag]), ProgramError::InvalidInstructionData);
+        }
+    }
+
+    #[test]
+    fn unpack_rejects_trailing_bytes() {
+        for instruction in all_variants() {
+            let mut data = instruction.pack();
+            data.push(0);
+            assert_eq!(unpack_err(&data), ProgramError::InvalidInstructionData);
+        }
+    }
+
+    #[test]
+    fn unpack_reports_the_truncated_field() {
+        // movie_id cut short
+        assert_eq!(unpack_err(&[0, 1, 0, 0]), ReviewError::InvalidMovieIdData.into());
+        assert_eq!(unpack_err(&[1]), ReviewError::InvalidMovieIdData.into());
+        assert_eq!(unpack_err(&[6, 1, 2, 3, 4, 5, 6, 7]), ReviewError::InvalidMovieIdData.into());
+
+        // movie_id present, rating missing
+        let mut data = vec![0];
+        data.extend_from_slice(&7u64.to_le_bytes());
+        assert_eq!(unpack_err(&data), ReviewError::InvalidRatingData.into());
+
+        // rating present, description missing
+        data.push(5);
+        assert_eq!(unpack_err(&data), ReviewError::InvalidDescriptionData.into());
+
+        assert_eq!(unpack_err(&[2, 1, 0]), ReviewError::InvalidCommentData.into());
+        assert_eq!(unpack_err(&[5]), ReviewError::InvalidTitleData.into());
+        assert_eq!(unpack_err(&[10]), ReviewError::InvalidVoteData.into());
+        assert_eq!(unpack_err(&[12, 1, 2, 3]), ReviewError::InvalidConfigData.into());
+        assert_eq!(unpack_err(&[14]), ReviewError::InvalidMetadataData.into());
+
+        // title present, year cut short, then imdb_id missing
+        let mut data = vec![5];
+        data.extend(string_with_len(4, b"Heat"));
+        data.push(0xcb);
+        assert_eq!(unpack_err(&data), ReviewError::InvalidYearData.into());
+        data.push(0x07);
+        assert_eq!(unpack_err(&data), ReviewError::InvalidExternalIdData.into());
+    }
+
+    #[test]
+    fn unpack_rejects_oversized_length_prefixes() {
+        let mut data = vec![5];
+        data.extend(string_with_len(u32::MAX, b"Heat"));
+        assert_eq!(unpack_err(&data), ReviewError::InvalidTitleData.into());
+
+        let mut data = vec![2];
+        data.extend(string_with_len(1_000, b"short"));
+        assert_eq!(unpack_err(&data), ReviewError::InvalidCommentData.into());
+
+        let mut data = vec![0];
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.push(5);
+        data.extend(string_with_len(u32::MAX, &[]));
+        assert_eq!(unpack_err(&data), ReviewError::InvalidDescriptionData.into());
+
+        let mut data = vec![14];
+        data.extend(string_with_len(3, b"MRT"));
+        data.extend(string_with_len(u32::MAX - 1, b"MRT"));
+        assert_eq!(unpack_err(&data), ReviewError::InvalidMetadataData.into());
+    }
+
+    #[test]
+    fn unpack_rejects_invalid_field_values() {
+        // A bool that isn't 0 or 1
+        assert_eq!(unpack_err(&[10, 2]), ReviewError::InvalidVoteData.into());
+
+        // A description that isn't UTF-8
+        let mut data = vec![0];
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.push(5);
+        data.extend(string_with_len(2, &[0xff, 0xfe]));
+        assert_eq!(unpack_err(&data), ReviewError::InvalidDescriptionData.into());
+
+        // An unknown SelfCommentPolicy tag
+        let mut data = MovieInstruction::InitializeConfig {
+            review_reward: RewardSetting { amount: 1, enabled: true },
+            comment_reward: RewardSetting { amount: 1, enabled: true },
+            reward_limits: RewardLimits { cap_per_window: 1, window_slots: 1, cooldown_slots: 1 },
+            self_comment_policy: SelfCommentPolicy::Reject,
+        }
+        .pack();
+        *data.last_mut().unwrap() = 9;
+        assert_eq!(unpack_err(&data), ReviewError::InvalidConfigData.into());
+    }
+
+    #[test]
+    fn unpack_never_panics_on_random_bytes() {
+        // xorshift64, so the input is the same on every run
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        for _ in 0..20_000 {
+            let len = (next() % 64) as usize;
+            let mut data: Vec<u8> = (0..len).map(|_| next() as u8).collect();
+            // Keep most inputs on a real tag so the field decoders get exercised
+            if let Some(tag) = data.first_mut() {
+                *tag %= 17;
+            }
+
+            // The derived decoder is the reference layout, so a variant added to
+            // the enum but not to unpack shows up as a disagreement
+            let unpacked = MovieInstruction::unpack(&data);
+            assert_eq!(MovieInstruction::try_from_slice(&data).is_ok(), unpacked.is_ok(), "{:?}", data);
+
+            if let Ok(instruction) = unpacked {
+                assert_eq!(instruction.pack(), data);
+            }
+        }
+    }
+}
+
@@ -1,5 +1,10 @@
+#[cfg(not(feature = "no-entrypoint"))]
 pub mod entrypoint;
 pub mod instruction;
 pub mod state;
 pub mod processor;
 pub mod error;
+pub mod pda;
+pub mod validation;
+pub mod audit;
+pub mod metadata;
@@ -0,0 +1,124 @@
+use borsh::BorshSerialize;
+use solana_program::{
+    instruction::{AccountMeta, Instruction},
+    pubkey,
+    pubkey::Pubkey,
+    system_program::ID as SYSTEM_PROGRAM_ID,
+    sysvar::rent::ID as RENT_PROGRAM_ID,
+};
+
+// The Metaplex Token Metadata program. Its client crate doesn't build against
+// this solana-program version, so the one instruction we need is encoded here.
+pub const TOKEN_METADATA_PROGRAM_ID: Pubkey = pubkey!("metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+
+// Instruction index of CreateMetadataAccountV3 in the Token Metadata program
+const CREATE_METADATA_ACCOUNT_V3: u8 = 33;
+
+// CreateMetadataAccountArgsV3 with its DataV2 inlined. The fields this program
+// never sets are always None, so their inner layout doesn't matter here
+#[derive(BorshSerialize)]
+struct CreateMetadataAccountArgsV3 {
+    name: String,
+    symbol: String,
+    uri: String,
+    seller_fee_basis_points: u16,
+    creators: Option<()>,
+    collection: Option<()>,
+    uses: Option<()>,
+    is_mutable: bool,
+    collection_details: Option<()>,
+}
+
+// Creates the metadata account for `mint`, with `update_authority` allowed to
+// change it later. Both authorities have to sign
+#[allow(clippy::too_many_arguments)]
+pub fn create_metadata_account_v3(
+    metadata: &Pubkey,
+    mint: &Pubkey,
+    mint_authority: &Pubkey,
+    payer: &Pubkey,
+    update_authority: &Pubkey,
+    name: String,
+    symbol: String,
+    uri: String,
+) -> Instruction {
+    let args = CreateMetadataAccountArgsV3 {
+        name,
+        symbol,
+        uri,
+        seller_fee_basis_points: 0,
+        creators: None,
+        collection: None,
+        uses: None,
+        is_mutable: true,
+        collection_details: None,
+    };
+
+    let mut data = vec![CREATE_METADATA_ACCOUNT_V3];
+    data.extend(args.try_to_vec().unwrap());
+
+    Instruction {
+        program_id: TOKEN_METADATA_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(*metadata, false),
+            AccountMeta::new_readonly(*mint, false),
+            AccountMeta::new_readonly(*mint_authority, true),
+            AccountMeta::new(*payer, true),
+            AccountMeta::new_readonly(*update_authority, true),
+            AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+            AccountMeta::new_readonly(RENT_PROGRAM_ID, false),
+        ],
+        data,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_metadata_account_v3_matches_the_token_metadata_encoding() {
+        let [metadata, mint, mint_authority, payer, update_authority] = [(); 5].map(|_| Pubkey::new_unique());
+        let ix = create_metadata_account_v3(
+            &metadata,
+            &mint,
+            &mint_authority,
+            &payer,
+            &update_authority,
+            "Movie".to_string(),
+            "MOV".to_string(),
+            "https://x.io".to_string(),
+        );
+
+        // Written out by hand from the Token Metadata program's
+        // CreateMetadataAccountV3 rather than derived from our struct
+        let expected: Vec<u8> = [
+            &[33][..],                                    // instruction index
+            &[5, 0, 0, 0], b"Movie",                      // name
+            &[3, 0, 0, 0], b"MOV",                        // symbol
+            &[12, 0, 0, 0], b"https://x.io",              // uri
+            &[0, 0],                                      // seller_fee_basis_points
+            &[0],                                         // creators: None
+            &[0],                                         // collection: None
+            &[0],                                         // uses: None
+            &[1],                                         // is_mutable
+            &[0],                                         // collection_details: None
+        ]
+        .concat();
+
+        assert_eq!(ix.program_id.to_string(), "metaqbxxUerdq28cj1RbAWkYQm3ybzjb6a8bt518x1s");
+        assert_eq!(ix.data, expected);
+        assert_eq!(
+            ix.accounts,
+            vec![
+                AccountMeta::new(metadata, false),
+                AccountMeta::new_readonly(mint, false),
+                AccountMeta::new_readonly(mint_authority, true),
+                AccountMeta::new(payer, true),
+                AccountMeta::new_readonly(update_authority, true),
+                AccountMeta::new_readonly(SYSTEM_PROGRAM_ID, false),
+                AccountMeta::new_readonly(RENT_PROGRAM_ID, false),
+            ]
+        );
+    }
+}
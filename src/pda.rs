@@ -0,0 +1,143 @@
+use solana_program::{bpf_loader_upgradeable, hash::hash, program_error::ProgramError, pubkey::Pubkey};
+
+use crate::error::ReviewError;
+use crate::metadata::TOKEN_METADATA_PROGRAM_ID;
+
+// Every address the program derives lives here so the processor and the
+// client-side instruction builders can never disagree on the seeds.
+
+// Review account - seeded by the reviewer and the registered movie's id
+pub fn find_review_address(program_id: &Pubkey, reviewer: &Pubkey, movie_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[reviewer.as_ref(), movie_id.to_be_bytes().as_ref()], program_id)
+}
+
+// Where reviews lived before the movie registry - seeded by the reviewer and
+// the free-text title. Only the migrate instruction still uses it.
+// The title comes from the instruction, so one too long to be a seed is an
+// error instead of a panic
+pub fn find_legacy_review_address(
+    program_id: &Pubkey,
+    reviewer: &Pubkey,
+    title: &str,
+) -> Result<(Pubkey, u8), ProgramError> {
+    Pubkey::try_find_program_address(&[reviewer.as_ref(), title.as_bytes()], program_id)
+        .ok_or_else(|| ReviewError::InvalidPDA.into())
+}
+
+// Reviewer state - one per wallet, seeded by "reviewer" and the wallet
+pub fn find_reviewer_address(program_id: &Pubkey, reviewer: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"reviewer", reviewer.as_ref()], program_id)
+}
+
+// Comment anchor of a reviewer's nth review - seeded by "comment_anchor", the
+// reviewer and n. Nothing lives at this address, it only seeds the review's
+// comment counter, comments and votes
+pub fn find_comment_anchor_address(program_id: &Pubkey, reviewer: &Pubkey, review_index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"comment_anchor", reviewer.as_ref(), review_index.to_be_bytes().as_ref()],
+        program_id,
+    )
+}
+
+// Comment counter - one per review, seeded by its comment anchor and "comment"
+pub fn find_comment_counter_address(program_id: &Pubkey, review: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[review.as_ref(), b"comment"], program_id)
+}
+
+// Comment account - seeded by the review's comment anchor and the comment's index
+pub fn find_comment_address(program_id: &Pubkey, review: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[review.as_ref(), index.to_be_bytes().as_ref()], program_id)
+}
+
+// Reply counter - one per comment, created on its first reply and seeded by
+// the comment address and "reply"
+pub fn find_reply_counter_address(program_id: &Pubkey, comment: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[comment.as_ref(), b"reply"], program_id)
+}
+
+// Reply - seeded by the parent comment's address and the reply's index
+pub fn find_reply_address(program_id: &Pubkey, parent_comment: &Pubkey, index: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[parent_comment.as_ref(), index.to_be_bytes().as_ref()], program_id)
+}
+
+// Vote - one per voter and review, seeded by "vote", the review's comment
+// anchor and the voter
+pub fn find_vote_address(program_id: &Pubkey, comment_anchor: &Pubkey, voter: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"vote", comment_anchor.as_ref(), voter.as_ref()], program_id)
+}
+
+// Vote tally - one per review, seeded by its comment anchor and "votes"
+pub fn find_vote_tally_address(program_id: &Pubkey, comment_anchor: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[comment_anchor.as_ref(), b"votes"], program_id)
+}
+
+// Reward token mint - the seed is just "token_mint"
+pub fn find_mint_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"token_mint"], program_id)
+}
+
+// Reward token mint authority - the seed is just "token_auth"
+pub fn find_mint_authority_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"token_auth"], program_id)
+}
+
+// Reward ledger - one per wallet, seeded by "reward_ledger" and the wallet
+pub fn find_reward_ledger_address(program_id: &Pubkey, user: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"reward_ledger", user.as_ref()], program_id)
+}
+
+// Reward mint's Metaplex metadata - derived by the Token Metadata program
+// from "metadata", its own id and the mint
+pub fn find_metadata_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"metadata", TOKEN_METADATA_PROGRAM_ID.as_ref(), mint.as_ref()],
+        &TOKEN_METADATA_PROGRAM_ID,
+    )
+}
+
+// This program's ProgramData account - derived by the upgradeable BPF loader
+// from the program id alone
+pub fn find_program_data_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[program_id.as_ref()], &bpf_loader_upgradeable::id())
+}
+
+// Program config - the seed is just "config"
+pub fn find_config_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"config"], program_id)
+}
+
+// Titles are compared case-insensitively with whitespace collapsed, so
+// "Inception" and " inception " can only be registered once
+pub fn normalize_title(title: &str) -> String {
+    title.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase()
+}
+
+// Per-movie rating aggregate - seeded by "aggregate" and the movie id
+pub fn find_aggregate_address(program_id: &Pubkey, movie_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"aggregate", movie_id.to_be_bytes().as_ref()], program_id)
+}
+
+// Movie registry - hands out movie ids, the seed is just "movie_registry"
+pub fn find_movie_registry_address(program_id: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"movie_registry"], program_id)
+}
+
+// Registered movie - seeded by "movie" and its id
+pub fn find_movie_address(program_id: &Pubkey, movie_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"movie", movie_id.to_be_bytes().as_ref()], program_id)
+}
+
+// A seed can be at most 32 bytes, so titles are seeded by the sha256 of their
+// normalized form, which fits any title
+pub fn hash_title(title: &str) -> [u8; 32] {
+    hash(normalize_title(title).as_bytes()).to_bytes()
+}
+
+// Title index - maps a normalized title and release year to the movie's id,
+// so the same film can't be registered twice
+pub fn find_movie_title_address(program_id: &Pubkey, title: &str, year: u16) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"movie_title", hash_title(title).as_ref(), year.to_be_bytes().as_ref()],
+        program_id,
+    )
+}
@@ -4,22 +4,37 @@ use solana_program::{
     entrypoint::ProgramResult,
     msg,
     system_instruction,
-    sysvar::{rent::Rent, Sysvar, rent::ID as RENT_PROGRAM_ID},
-    program::invoke_signed,
+    sysvar::{clock::Clock, rent::Rent, Sysvar},
+    program::{invoke, invoke_signed},
     borsh::try_from_slice_unchecked, 
     program_error::ProgramError, program_pack::IsInitialized,
-    system_program::ID as SYSTEM_PROGRAM_ID,
-    native_token::LAMPORTS_PER_SOL
 };
-use spl_associated_token_account::get_associated_token_address;
-use spl_token::{instruction::initialize_mint, ID as TOKEN_PROGRAM_ID};
+use spl_associated_token_account::instruction::create_associated_token_account;
+use spl_token::instruction::initialize_mint;
 
 use std::convert::TryInto;
 use borsh::BorshSerialize;
 
 use crate::instruction::MovieInstruction;
-use crate::state::{MovieAccountState, MovieComment, MovieCommentCounter};
+use crate::state::{
+    Config, LegacyMovieAccountState, LegacyMovieCommentCounter, Movie, MovieAccountState, MovieAggregate,
+    MovieComment, MovieCommentCounter, MovieRegistry, MovieReplyCounter, MovieTitleIndex, MovieVote,
+    MovieVoteTally, ProgramAccount, ReviewerState, RewardLedger, RewardLimits, RewardSetting,
+    SelfCommentPolicy,
+};
 use crate::error::ReviewError;
+use crate::metadata::create_metadata_account_v3;
+use crate::pda::{
+    find_aggregate_address, find_comment_address, find_comment_anchor_address, find_comment_counter_address,
+    find_config_address, find_legacy_review_address, find_metadata_address, find_mint_address, find_mint_authority_address, find_movie_address,
+    find_movie_registry_address, find_movie_title_address, find_reply_address,
+    find_reply_counter_address, find_review_address, find_reviewer_address, find_vote_address,
+    find_vote_tally_address, hash_title,
+};
+use crate::validation::{
+    check_program_owned, check_upgrade_authority, load_config, Pda, ProgramOwned, RentSysvar, RewardAccounts, Signer, SystemProgram,
+    TokenMetadataProgram, TokenProgram,
+};
 
 pub fn process_instruction(
     program_id: &Pubkey,
@@ -29,83 +44,150 @@ pub fn process_instruction(
     let instruction = MovieInstruction::unpack(instruction_data)?;
 
     match instruction {
-        MovieInstruction::AddMovieReview { title, rating, description } => {
-            add_movie_review(program_id, accounts, title, rating, description)
+        MovieInstruction::AddMovieReview { movie_id, rating, description } => {
+            add_movie_review(program_id, accounts, movie_id, rating, description)
         },
         // add UpdateMovieReview to match against our new data structure
-        MovieInstruction::UpdateMovieReview { title, rating, description } => {
+        MovieInstruction::UpdateMovieReview { movie_id, rating, description } => {
             // make call to update function that we'll define next
-            update_movie_review(program_id, accounts, title, rating, description)
+            update_movie_review(program_id, accounts, movie_id, rating, description)
         },
         MovieInstruction::AddComments { comment } => {
             add_comment(program_id, accounts, comment)
         },
         // New instruction handled here to initialize the mint account
         MovieInstruction::InitializeMint => initialize_token_mint(program_id, accounts),
+        MovieInstruction::DeleteMovieReview => delete_movie_review(program_id, accounts),
+        MovieInstruction::RegisterMovie { title, year, imdb_id } => {
+            register_movie(program_id, accounts, title, year, imdb_id)
+        },
+        MovieInstruction::MigrateReview { movie_id } => {
+            migrate_review(program_id, accounts, movie_id)
+        },
+        MovieInstruction::UpdateComment { comment } => {
+            update_comment(program_id, accounts, Some(comment))
+        },
+        MovieInstruction::DeleteComment => update_comment(program_id, accounts, None),
+        MovieInstruction::ReplyToComment { comment } => {
+            reply_to_comment(program_id, accounts, comment)
+        },
+        MovieInstruction::VoteOnReview { helpful } => {
+            vote_on_review(program_id, accounts, Some(helpful))
+        },
+        MovieInstruction::WithdrawVote => vote_on_review(program_id, accounts, None),
+        MovieInstruction::InitializeConfig {
+            review_reward,
+            comment_reward,
+            reward_limits,
+            self_comment_policy,
+        } => initialize_config(
+            program_id,
+            accounts,
+            review_reward,
+            comment_reward,
+            reward_limits,
+            self_comment_policy,
+        ),
+        MovieInstruction::UpdateConfig {
+            admin,
+            review_reward,
+            comment_reward,
+            reward_limits,
+            self_comment_policy,
+        } => update_config(
+            program_id,
+            accounts,
+            admin,
+            review_reward,
+            comment_reward,
+            reward_limits,
+            self_comment_policy,
+        ),
+        MovieInstruction::CreateMintMetadata { name, symbol, uri } => {
+            create_mint_metadata(program_id, accounts, name, symbol, uri)
+        },
     }
 }
 
 pub fn add_movie_review(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    title: String,
+    movie_id: u64,
     rating: u8,
     description: String,
 ) -> ProgramResult {
     msg!("Adding movie review...");
-    msg!("Title: {}", title);
+    msg!("Movie id: {}", movie_id);
     msg!("Rating: {}", rating);
     msg!("Description: {}", description);
 
     let account_info_iter = &mut accounts.iter();
 
-    let initializer = next_account_info(account_info_iter)?;
-    let pda_account = next_account_info(account_info_iter)?;
-    let system_program = next_account_info(account_info_iter)?;
+    // ensure that the initializer of a review is also a signer on the transaction.
+    let initializer = Signer::new(next_account_info(account_info_iter)?)?;
+    // make sure the pda_account passed in by the user is the pda we expect
+    let pda_account = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_review_address(program_id, initializer.key, movie_id),
+    )?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
 
-    // New accout to store comment count
+    // New accout to store comment count, checked once the comment anchor is known
     let pda_counter = next_account_info(account_info_iter)?;
+    // Rating totals for every review of this movie, created on its first review
+    let pda_aggregate = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_aggregate_address(program_id, movie_id),
+    )?;
+    // Reviews can only be written for registered movies
+    let pda_movie = next_account_info(account_info_iter)?;
+    let movie_data = ProgramOwned::<Movie>::new(pda_movie, program_id)?.data;
+    Pda::new(pda_movie, find_movie_address(program_id, movie_id))?;
+    msg!("Title: {}", movie_data.title);
+    // How many reviews the initializer has written, created with their first
+    let pda_reviewer = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_reviewer_address(program_id, initializer.key),
+    )?;
+    // Sets the reward
+    let config = load_config(program_id, next_account_info(account_info_iter)?)?;
 
-    // token_mint - the mint address of the token
-    let token_mint = next_account_info(account_info_iter)?;
-    // mint_auth - address of the authority of the token mint
-    let mint_auth = next_account_info(account_info_iter)?;
-    // user_ata - user’s associated token account for this mint (where the tokens will be minted)
-    let user_ata = next_account_info(account_info_iter)?;
-    // token_program - address of the token program
-    let token_program = next_account_info(account_info_iter)?;
-
-    // ensure that the initializer of a review is also a signer on the transaction.
-    if !initializer.is_signer {
-        msg!("Missing required signature");
-        return Err(ProgramError::MissingRequiredSignature)
-    }
+    // token_mint, mint_auth, the user's associated token account for this
+    // mint (where the tokens will be minted), the token program, the user's
+    // reward ledger and the associated token program. Left off by clients
+    // that don't want a reward
+    let rewards = RewardAccounts::optional(program_id, initializer.key, account_info_iter)?;
 
-    let (pda, bump_seed) = Pubkey::find_program_address(
-        &[
-            initializer.key.as_ref(),
-            title.as_bytes().as_ref(),
-        ], 
-        program_id
-    );
-    // make sure the pda_account passed in by the user is the pda we expect
-    if pda != *pda_account.key {
-        msg!("Invalid seeds for PDA");
-        return Err(ProgramError::InvalidArgument)
-    }
     // making sure rating falls within the 1 to 5 scale.
-    if rating > 5 || rating < 1 {
+    if !(1..=5).contains(&rating) {
         msg!("Rating cannot be higher than 5");
         return Err(ReviewError::InvalidRating.into())
     }
 
-    let account_len = 1000;
+    // Only pay rent for what the review actually needs
+    let account_len = MovieAccountState::get_account_size(&description);
 
-    if MovieAccountState::get_account_size(title.clone(), description.clone()) > account_len {
-        msg!("Data length is larger than 1000 bytes");
+    if account_len > MovieAccountState::MAX_SIZE {
+        msg!("Data length is larger than {} bytes", MovieAccountState::MAX_SIZE);
         return Err(ReviewError::InvalidDataLength.into());
     }
 
+    // The comment anchor comes from the reviewer's review count rather than
+    // the review's address, which a later review can reuse once this one is
+    // moved or deleted
+    let mut reviewer_data = if pda_reviewer.data_is_empty() {
+        ReviewerState {
+            discriminator: ReviewerState::DISCRIMINATOR,
+            is_initialized: true,
+            review_count: 0,
+        }
+    } else {
+        ProgramOwned::<ReviewerState>::new(&pda_reviewer, program_id)?.data
+    };
+    let (comment_anchor, _) = find_comment_anchor_address(program_id, initializer.key, reviewer_data.review_count);
+    let pda_counter = Pda::new(pda_counter, find_comment_counter_address(program_id, &comment_anchor))?;
+    msg!("Comment anchor: {}", comment_anchor);
+
     let rent = Rent::get()?;
     let rent_lamports = rent.minimum_balance(account_len);
 
@@ -125,13 +207,13 @@ pub fn add_movie_review(
         &[
             &[
                 initializer.key.as_ref(),
-                title.as_bytes().as_ref(),
-                &[bump_seed]
+                movie_id.to_be_bytes().as_ref(),
+                &[pda_account.bump]
             ]
         ]
     )?;
 
-    msg!("PDA created: {}", pda);
+    msg!("PDA created: {}", pda_account.key);
 
     msg!("unpacking state account");
     let mut account_data = try_from_slice_unchecked::<MovieAccountState>(
@@ -142,11 +224,12 @@ pub fn add_movie_review(
 
     msg!("borrowed account data");
 
-    account_data.description = MovieAccountState::DISCRIMINATOR.to_string();
+    account_data.discriminator = MovieAccountState::DISCRIMINATOR;
     account_data.reviewer = *initializer.key;
-    account_data.title = title;
+    account_data.movie_id = movie_id;
     account_data.rating = rating;
     account_data.description = description;
+    account_data.comment_anchor = comment_anchor;
     account_data.is_initialized = true;
 
     msg!("serializing account");
@@ -161,20 +244,6 @@ pub fn add_movie_review(
     let rent = Rent::get()?;
     let counter_rent_lamports = rent.minimum_balance(MovieCommentCounter::SIZE);
 
-    // Deriving the address and validating that the correct seeds were passed in
-    let (counter, counter_bump) = Pubkey::find_program_address(
-        &[
-            pda.as_ref(),
-            "comment".as_ref(),
-        ], 
-        program_id
-    );
-
-    if counter != *pda_counter.key {
-        msg!("Invalid seeds for PDA");
-        return Err(ProgramError::InvalidArgument);
-    }
-
     // Creating the comment counter account
     invoke_signed(
         &system_instruction::create_account(
@@ -192,9 +261,9 @@ pub fn add_movie_review(
         ],
         &[
             &[
-                pda.as_ref(),  // Seeds for the PDA
+                comment_anchor.as_ref(),  // Seeds for the PDA
                 "comment".as_ref(),  // The string "comment"
-                &[counter_bump]  // PDA account
+                &[pda_counter.bump]  // PDA account
             ]
         ],
     )?;
@@ -202,7 +271,7 @@ pub fn add_movie_review(
 
     // Deserialize the newly created counter account
     let mut counter_data = try_from_slice_unchecked::<MovieCommentCounter>(
-        &pda_account
+        &pda_counter
         .data
         .borrow()
     ).unwrap();
@@ -213,72 +282,77 @@ pub fn add_movie_review(
         return Err(ProgramError::AccountAlreadyInitialized);
     }
 
-    counter_data.discriminator = MovieCommentCounter::DISCRIMINATOR.to_string();
+    counter_data.discriminator = MovieCommentCounter::DISCRIMINATOR;
     counter_data.counter = 0;
+    counter_data.review = *pda_account.key;
     counter_data.is_initialized = true;
     msg!("comment count: {}", counter_data.counter);
 
     counter_data.serialize(
-        &mut &mut pda_account
+        &mut &mut pda_counter
         .data
         .borrow_mut()[..]
     )?;
     msg!("Comment counter initialized");
 
-
-    msg!("derving mint authority");
-    let (mint_pda, _mint_bump) = Pubkey::find_program_address(
-        &[b"token_mint"], 
-        program_id
-    );
-    let (mint_auth_pda, mint_auth_bump) = Pubkey::find_program_address(
-        &[b"token_auth"], 
-        program_id
-    );
-
-    if *token_mint.key != mint_pda {
-        msg!("Incorrent token mint");
-        return Err(ReviewError::IncorrectAccountError.into());
-
+    if pda_reviewer.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                initializer.key,
+                pda_reviewer.key,
+                rent.minimum_balance(ReviewerState::SIZE),
+                ReviewerState::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                initializer.clone(),
+                pda_reviewer.clone(),
+                system_program.clone(),
+            ],
+            &[
+                &[
+                    b"reviewer",
+                    initializer.key.as_ref(),
+                    &[pda_reviewer.bump]
+                ]
+            ],
+        )?;
+        msg!("Created reviewer account: {}", pda_reviewer.key);
     }
 
-    if *mint_auth.key != mint_auth_pda {
-        msg!("Mint passed in and mint derived do not match");
-        return Err(ReviewError::InvalidPDA.into());
-    }
-    
-    if *user_ata.key != get_associated_token_address(initializer.key, token_mint.key) {
-        msg!("Incorrect token mint");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
-    
-    if *token_program.key != TOKEN_PROGRAM_ID {
-        msg!("Incorrect token program");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
+    reviewer_data.review_count += 1;
+    reviewer_data.serialize(
+        &mut &mut pda_reviewer
+        .data
+        .borrow_mut()[..]
+    )?;
 
-    msg!("Minting 10 tokens to User associated token account");
-    invoke_signed(
-        &spl_token::instruction::mint_to(
-            token_program.key,
-            token_mint.key,
-            user_ata.key,
-            mint_auth.key,
-            &[],
-            10*LAMPORTS_PER_SOL,
-        )?, // ? unwraps and returns the error if there is one
-        &[
-            token_mint.clone(),
-            user_ata.clone(),
-            mint_auth.clone(),
-        ], 
-        &[
-            &[
-                b"token_auth",
-                &[mint_auth_bump]
-            ]
-        ],
+    let mut aggregate_data = load_or_create_aggregate(
+        program_id,
+        &pda_aggregate,
+        &initializer,
+        &system_program,
+        movie_id,
     )?;
+    aggregate_data.add_rating(rating);
+    aggregate_data.serialize(
+        &mut &mut pda_aggregate
+        .data
+        .borrow_mut()[..]
+    )?;
+    msg!("Movie now has {} reviews", aggregate_data.review_count);
+
+    match &rewards {
+        Some(rewards) => mint_reward(
+            program_id,
+            rewards,
+            &config.reward_limits,
+            &initializer,
+            &system_program,
+            config.review_reward.payout(),
+        )?,
+        None => log_reward_skipped("review", initializer.key),
+    }
 
     Ok(())
 }
@@ -286,7 +360,7 @@ pub fn add_movie_review(
 pub fn update_movie_review(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
-    _title: String,
+    movie_id: u64,
     rating: u8,
     description: String
 ) -> ProgramResult {
@@ -296,53 +370,65 @@ pub fn update_movie_review(
     let account_info_iter = &mut accounts.iter();
 
     // Get accounts
-    let initializer = next_account_info(account_info_iter)?;
+    let initializer = Signer::new(next_account_info(account_info_iter)?)?;
     let pda_account = next_account_info(account_info_iter)?;
-		
-    // This is a good time to check that the pda_account.owner is the same as the program_id
-    if pda_account.owner != program_id {
-        return Err(ProgramError::IllegalOwner)
-    }
-
-    // check that the signer is the same as the initializer
-    if !initializer.is_signer {
-        msg!("Missing required signature");
-        return Err(ProgramError::MissingRequiredSignature)
-    }
-
-    // unpack the data from the pda_account
-    msg!("unpacking state account");
-    let mut account_data = try_from_slice_unchecked::<MovieAccountState>(
-        &pda_account
-        .data
-        .borrow()
-    ).unwrap();
-    msg!("borrowed account data");
+    // Needed to top up rent when the description grows
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+    let pda_aggregate = next_account_info(account_info_iter)?;
 
-    // Derive PDA and check that it matches client
-    let (pda, _bump_seed) = Pubkey::find_program_address(&[initializer.key.as_ref(), account_data.title.as_bytes().as_ref(),], program_id);
+    // unpack the data from the pda_account, checking it is an initialized
+    // review owned by the program
+    let mut account_data = ProgramOwned::<MovieAccountState>::new(pda_account, program_id)?.data;
 
-    if pda != *pda_account.key {
-        msg!("Invalid seeds for PDA");
-        return Err(ReviewError::InvalidPDA.into())
-    }
+    // Derive PDA and check that it matches client, which also ties the
+    // review to the signer
+    Pda::new(pda_account, find_review_address(program_id, initializer.key, account_data.movie_id))?;
 
-    if !account_data.is_initialized() {
-        msg!("Account is not initialized");
-        return Err(ReviewError::UninitializedAccount.into());
-    }
+    // The aggregate the review is currently counted in
+    let mut aggregate_data = ProgramOwned::<MovieAggregate>::new(pda_aggregate, program_id)?.data;
+    let pda_aggregate = Pda::new(pda_aggregate, find_aggregate_address(program_id, account_data.movie_id))?;
 
-    if rating > 5 || rating < 1 {
+    if !(1..=5).contains(&rating) {
         msg!("Rating cannot be higher than 5");
         return Err(ReviewError::InvalidRating.into())
     }
 
-    let total_len: usize = 1 + 1 + (4 + account_data.title.len()) + (4 + description.len());
-    if total_len > 1000 {
-        msg!("Data length is larger than 1000 bytes");
+    let new_len = MovieAccountState::get_account_size(&description);
+    if new_len > MovieAccountState::MAX_SIZE {
+        msg!("Data length is larger than {} bytes", MovieAccountState::MAX_SIZE);
         return Err(ReviewError::InvalidDataLength.into())
     }
 
+    if movie_id != account_data.movie_id {
+        // The review is seeded by its movie, so a new movie means a new account
+        return move_movie_review(
+            program_id,
+            account_info_iter,
+            &initializer,
+            pda_account,
+            &system_program,
+            &pda_aggregate,
+            aggregate_data,
+            account_data,
+            movie_id,
+            rating,
+            description,
+        );
+    }
+
+    aggregate_data.remove_rating(account_data.rating);
+    aggregate_data.add_rating(rating);
+    aggregate_data.serialize(
+        &mut &mut pda_aggregate
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    // Resize the account to fit the new description, the reviewer pays for
+    // any extra rent and gets back whatever is no longer needed
+    resize_account(pda_account, &initializer, &system_program, new_len)?;
+    msg!("Review account resized to {} bytes", new_len);
+
     // update the account info and serialize it to account
     account_data.rating = rating;
     account_data.description = description;
@@ -356,6 +442,196 @@ pub fn update_movie_review(
     Ok(())
 }
 
+// Moves a review to the address for another movie, for a review filed under
+// the wrong one. The old account is closed and refunded to the reviewer; the
+// comment counter and comments stay where they are, keyed by the review's
+// comment anchor, and the counter is pointed at the new address so comments
+// still resolve to the review.
+#[allow(clippy::too_many_arguments)]
+fn move_movie_review<'a, 'info>(
+    program_id: &Pubkey,
+    account_info_iter: &mut impl Iterator<Item = &'a AccountInfo<'info>>,
+    initializer: &'a AccountInfo<'info>,
+    pda_account: &'a AccountInfo<'info>,
+    system_program: &'a AccountInfo<'info>,
+    pda_aggregate: &'a AccountInfo<'info>,
+    mut aggregate_data: MovieAggregate,
+    mut account_data: MovieAccountState,
+    movie_id: u64,
+    rating: u8,
+    description: String,
+) -> ProgramResult {
+    msg!("Moving review to movie: {}", movie_id);
+
+    let new_pda_account = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_review_address(program_id, initializer.key, movie_id),
+    )?;
+    let pda_counter = next_account_info(account_info_iter)?;
+    let mut counter_data = ProgramOwned::<MovieCommentCounter>::new(pda_counter, program_id)?.data;
+    Pda::new(pda_counter, find_comment_counter_address(program_id, &account_data.comment_anchor))?;
+    let new_pda_aggregate = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_aggregate_address(program_id, movie_id),
+    )?;
+    let new_pda_movie = next_account_info(account_info_iter)?;
+    ProgramOwned::<Movie>::new(new_pda_movie, program_id)?;
+    Pda::new(new_pda_movie, find_movie_address(program_id, movie_id))?;
+
+    aggregate_data.remove_rating(account_data.rating);
+    aggregate_data.serialize(
+        &mut &mut pda_aggregate
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    let mut new_aggregate_data = load_or_create_aggregate(
+        program_id,
+        &new_pda_aggregate,
+        initializer,
+        system_program,
+        movie_id,
+    )?;
+    new_aggregate_data.add_rating(rating);
+    new_aggregate_data.serialize(
+        &mut &mut new_pda_aggregate
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    let account_len = MovieAccountState::get_account_size(&description);
+    let rent = Rent::get()?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            initializer.key,
+            new_pda_account.key,
+            rent.minimum_balance(account_len),
+            account_len.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            initializer.clone(),
+            new_pda_account.clone(),
+            system_program.clone(),
+        ],
+        &[
+            &[
+                initializer.key.as_ref(),
+                movie_id.to_be_bytes().as_ref(),
+                &[new_pda_account.bump]
+            ]
+        ]
+    )?;
+
+    msg!("PDA created: {}", new_pda_account.key);
+
+    account_data.movie_id = movie_id;
+    account_data.rating = rating;
+    account_data.description = description;
+
+    account_data.serialize(
+        &mut &mut new_pda_account
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    // Forward pointer for anything that still only knows the old address
+    counter_data.review = *new_pda_account.key;
+    counter_data.serialize(
+        &mut &mut pda_counter
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    close_account(pda_account, initializer)?;
+    msg!("Review moved from {}", pda_account.key);
+
+    Ok(())
+}
+
+// Loads the aggregate for `movie_id`, creating it on the movie's first review
+fn load_or_create_aggregate<'info>(
+    program_id: &Pubkey,
+    pda_aggregate: &Pda<'_, 'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    movie_id: u64,
+) -> Result<MovieAggregate, ProgramError> {
+    if !pda_aggregate.data_is_empty() {
+        return Ok(ProgramOwned::<MovieAggregate>::new(pda_aggregate, program_id)?.data);
+    }
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            payer.key,
+            pda_aggregate.key,
+            rent.minimum_balance(MovieAggregate::SIZE),
+            MovieAggregate::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            payer.clone(),
+            AccountInfo::clone(pda_aggregate),
+            system_program.clone(),
+        ],
+        &[
+            &[
+                b"aggregate",
+                movie_id.to_be_bytes().as_ref(),
+                &[pda_aggregate.bump]
+            ]
+        ],
+    )?;
+    msg!("Created aggregate: {}", pda_aggregate.key);
+
+    Ok(MovieAggregate {
+        discriminator: MovieAggregate::DISCRIMINATOR,
+        is_initialized: true,
+        review_count: 0,
+        rating_sum: 0,
+        histogram: [0; 5],
+    })
+}
+
+// Reallocs a program owned account to `new_len`, with `payer` covering any
+// extra rent and getting back whatever is no longer needed
+fn resize_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    new_len: usize,
+) -> ProgramResult {
+    let rent = Rent::get()?;
+    let new_minimum_balance = rent.minimum_balance(new_len);
+    let current_lamports = account.lamports();
+
+    if new_minimum_balance > current_lamports {
+        invoke(
+            &system_instruction::transfer(
+                payer.key,
+                account.key,
+                new_minimum_balance - current_lamports,
+            ),
+            &[
+                payer.clone(),
+                account.clone(),
+                system_program.clone(),
+            ],
+        )?;
+    } else if current_lamports > new_minimum_balance {
+        let refund = current_lamports - new_minimum_balance;
+        **account.try_borrow_mut_lamports()? = new_minimum_balance;
+        **payer.try_borrow_mut_lamports()? = payer
+            .lamports()
+            .checked_add(refund)
+            .ok_or(ProgramError::InvalidArgument)?;
+    }
+
+    account.realloc(new_len, false)
+}
+
 pub fn add_comment(
     program_id: &Pubkey,
     accounts: &[AccountInfo],
@@ -366,34 +642,45 @@ pub fn add_comment(
 
     let account_info_iter = &mut accounts.iter();
 
-    let commenter = next_account_info(account_info_iter)?;
+    // Validate every account before anything is created or written
+    let commenter = Signer::new(next_account_info(account_info_iter)?)?;
+
+    // The review must be a real, initialized review owned by this program
+    // that lives at the address its own reviewer and movie derive
     let pda_review = next_account_info(account_info_iter)?;
-    let pda_counter = next_account_info(account_info_iter)?;
-    let pda_comment = next_account_info(account_info_iter)?;
-    let system_program = next_account_info(account_info_iter)?;
+    let review_data = ProgramOwned::<MovieAccountState>::new(pda_review, program_id)?.data;
+    Pda::new(pda_review, find_review_address(program_id, &review_data.reviewer, review_data.movie_id))?;
 
-    let token_mint = next_account_info(account_info_iter)?;
-    let mint_auth = next_account_info(account_info_iter)?;
-    let user_ata = next_account_info(account_info_iter)?;
-    let token_program = next_account_info(account_info_iter)?;
+    // The counter must be the one derived from this review's comment anchor
+    let comment_anchor = review_data.comment_anchor;
+    let pda_counter = next_account_info(account_info_iter)?;
+    let mut counter_data = ProgramOwned::<MovieCommentCounter>::new(pda_counter, program_id)?.data;
+    Pda::new(pda_counter, find_comment_counter_address(program_id, &comment_anchor))?;
 
-    let mut counter_data = try_from_slice_unchecked::<MovieCommentCounter>(
-        &pda_counter
-        .data
-        .borrow()
-    ).unwrap();
+    // The new comment goes at the counter's current index
+    let pda_comment = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_comment_address(program_id, &comment_anchor, counter_data.counter),
+    )?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+    // Always required, its self-comment policy applies with or without a reward
+    let config = load_config(program_id, next_account_info(account_info_iter)?)?;
+
+    let rewards = RewardAccounts::optional(program_id, commenter.key, account_info_iter)?;
+
+    // Reviewers commenting on their own review are handled by the config's
+    // self-comment policy
+    let is_self_comment = review_data.reviewer == *commenter.key;
+    if is_self_comment && config.self_comment_policy == SelfCommentPolicy::Reject {
+        msg!("Reviewers cannot comment on their own review");
+        return Err(ReviewError::SelfCommentRejected.into());
+    }
 
-    let account_len = MovieComment::get_account_size(comment.clone());
+    let account_len = MovieComment::get_account_size(&comment, false);
 
     let rent = Rent::get()?;
     let rent_lamports = rent.minimum_balance(account_len);
 
-    let (pda, bump_seed) = Pubkey::find_program_address(&[pda_review.key.as_ref(), counter_data.counter.to_be_bytes().as_ref(),], program_id);
-    if pda != *pda_comment.key {
-        msg!("Invalid seeds for PDA");
-        return Err(ReviewError::InvalidPDA.into())
-    }
-
     invoke_signed(
         &system_instruction::create_account(
             commenter.key,
@@ -409,9 +696,9 @@ pub fn add_comment(
         ],
         &[
             &[
-                pda_review.key.as_ref(), 
+                comment_anchor.as_ref(), 
                 counter_data.counter.to_be_bytes().as_ref(), 
-                &[bump_seed]
+                &[pda_comment.bump]
             ]
         ],
     )?;
@@ -431,10 +718,13 @@ pub fn add_comment(
         return Err(ProgramError::AccountAlreadyInitialized);
     }
 
-    comment_data.discriminator = MovieComment::DISCRIMINATOR.to_string();
-    comment_data.reviewer = *pda_review.key;
+    comment_data.discriminator = MovieComment::DISCRIMINATOR;
+    // Comments always name the anchor, the counter forwards it to the review
+    comment_data.reviewer = comment_anchor;
     comment_data.commenter = *commenter.key;
     comment_data.comment = comment;
+    // The index the comment is seeded by, so it can be found from its data
+    comment_data.count = counter_data.counter;
     comment_data.is_initialized = true;
 
     comment_data.serialize(
@@ -453,127 +743,887 @@ pub fn add_comment(
 
 
     // Mint tokens here
-    msg!("deriving mint authority");
-    let (mint_pda, _mint_bump) = Pubkey::find_program_address(&[b"token_mint"], program_id);
-    let (mint_auth_pda, mint_auth_bump) =
-        Pubkey::find_program_address(&[b"token_auth"], program_id);
-
-    if *token_mint.key != mint_pda {
-        msg!("Incorrect token mint");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
-
-    if *mint_auth.key != mint_auth_pda {
-        msg!("Mint passed in and mint derived do not match");
-        return Err(ReviewError::InvalidPDA.into());
-    }
-
-    if *user_ata.key != get_associated_token_address(commenter.key, token_mint.key) {
-        msg!("Incorrect token mint");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
-
-    if *token_program.key != TOKEN_PROGRAM_ID {
-        msg!("Incorrect token program");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
-
-    msg!("Minting 5 tokens to User associated token account");
-    invoke_signed(
-        // Instruction
-        &spl_token::instruction::mint_to(
-            token_program.key,
-            token_mint.key,
-            user_ata.key,
-            mint_auth.key,
-            &[],
-            5 * LAMPORTS_PER_SOL,
+    match &rewards {
+        Some(rewards) => mint_reward(
+            program_id,
+            rewards,
+            &config.reward_limits,
+            &commenter,
+            &system_program,
+            config.comment_payout(is_self_comment),
         )?,
-        // Account_infos
-        &[token_mint.clone(), user_ata.clone(), mint_auth.clone()],
-        // Seeds
-        &[&[b"token_auth", &[mint_auth_bump]]],
-    )?;
+        None => log_reward_skipped("comment", commenter.key),
+    }
 
     Ok(())
 }
 
+// Adds a reply under an existing comment of the same review. Replies are
+// comments too, so they can be edited, deleted and replied to, down to
+// MovieComment::MAX_DEPTH. They don't earn the comment reward.
+pub fn reply_to_comment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    comment: String
+) -> ProgramResult {
+    msg!("Adding Reply...");
+    msg!("Comment: {}", comment);
 
-// At a high level, next steps are:
-// 1. Iterate through list of accounts to extract them
-// 2. Derive token mint PDA
-// 3. Validate all of the important accounts passed in:
-//      1. Token mint account
-//      2. Mint authority account
-//      3. System program
-//      4. Token program
-//      5. Sysvar rent - the rent calculation account
-// 4. Calculate rent for the mint account
-// 5. Create the token mint PDA
-// 6. Initialize the mint account
-
-pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
     let account_info_iter = &mut accounts.iter();
 
-    // The order of accounts is not arbitrary, the client will send them in this order
-    // Whoever sent in the transaction
-    let initializer = next_account_info(account_info_iter)?;
-    // Token mint PDA - derived on the client
-    let token_mint = next_account_info(account_info_iter)?;
-    // Token mint authority
-    let mint_auth = next_account_info(account_info_iter)?;
-    // System program to create a new account
-    let system_program = next_account_info(account_info_iter)?;
-    // Solana Token program address
-    let token_program = next_account_info(account_info_iter)?;
-    // System account to calcuate the rent
-    let sysvar_rent = next_account_info(account_info_iter)?;
-
-    // Derive the mint PDA again so we can validate it
-    // The seed is just "token_mint"
-    let (mint_pda, mint_bump) = Pubkey::find_program_address(&[b"token_mint"], program_id);
-    // Derive the mint authority so we can validate it
-    // The seed is just "token_auth"
-    let (mint_auth_pda, _mint_auth_bump) =
-        Pubkey::find_program_address(&[b"token_auth"], program_id);
-
-    msg!("Token mint: {:?}", mint_pda);
-    msg!("Mint authority: {:?}", mint_auth_pda);
-
-    // Validate the important accounts passed in
-    if mint_pda != *token_mint.key {
-        msg!("Incorrect token mint account");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
+    let commenter = Signer::new(next_account_info(account_info_iter)?)?;
 
-    if *token_program.key != TOKEN_PROGRAM_ID {
-        msg!("Incorrect token program");
-        return Err(ReviewError::IncorrectAccountError.into());
-    }
+    let pda_review = next_account_info(account_info_iter)?;
+    let review_data = ProgramOwned::<MovieAccountState>::new(pda_review, program_id)?.data;
+    Pda::new(pda_review, find_review_address(program_id, &review_data.reviewer, review_data.movie_id))?;
 
-    if *mint_auth.key != mint_auth_pda {
-        msg!("Incorrect mint auth account");
-        return Err(ReviewError::IncorrectAccountError.into());
+    // The parent must be a live comment on this review, which every comment
+    // and reply records through the review's comment anchor
+    let parent_comment = next_account_info(account_info_iter)?;
+    let parent_data = ProgramOwned::<MovieComment>::new(parent_comment, program_id)?.data;
+
+    if parent_data.reviewer != review_data.comment_anchor {
+        msg!("Parent comment is not on this review");
+        return Err(ReviewError::ParentCommentMismatch.into());
     }
 
-    if *system_program.key != SYSTEM_PROGRAM_ID {
-        msg!("Incorrect system program");
-        return Err(ReviewError::IncorrectAccountError.into());
+    if parent_data.is_deleted {
+        msg!("Parent comment has been deleted");
+        return Err(ReviewError::CommentDeleted.into());
     }
 
-    if *sysvar_rent.key != RENT_PROGRAM_ID {
-        msg!("Incorrect rent program");
-        return Err(ReviewError::IncorrectAccountError.into());
+    let depth = parent_data.depth + 1;
+    if depth > MovieComment::MAX_DEPTH {
+        msg!("Replies cannot be nested more than {} deep", MovieComment::MAX_DEPTH);
+        return Err(ReviewError::ReplyTooDeep.into());
     }
 
-    // Calculate the rent
+    let pda_reply_counter = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_reply_counter_address(program_id, parent_comment.key),
+    )?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+
     let rent = Rent::get()?;
-    // We know the size of a mint account is 82 (remember it lol)
-    let rent_lamports = rent.minimum_balance(82);
 
-    // Create the token mint PDA
-    invoke_signed(
-        &system_instruction::create_account(
+    // The parent's reply counter is created by its first reply
+    let mut counter_data = if pda_reply_counter.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                commenter.key,
+                pda_reply_counter.key,
+                rent.minimum_balance(MovieReplyCounter::SIZE),
+                MovieReplyCounter::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                commenter.clone(),
+                pda_reply_counter.clone(),
+                system_program.clone(),
+            ],
+            &[&[parent_comment.key.as_ref(), b"reply", &[pda_reply_counter.bump]]],
+        )?;
+        msg!("Created reply counter: {}", pda_reply_counter.key);
+
+        MovieReplyCounter {
+            discriminator: MovieReplyCounter::DISCRIMINATOR,
+            is_initialized: true,
+            counter: 0,
+        }
+    } else {
+        ProgramOwned::<MovieReplyCounter>::new(&pda_reply_counter, program_id)?.data
+    };
+
+    let pda_reply = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_reply_address(program_id, parent_comment.key, counter_data.counter),
+    )?;
+
+    let account_len = MovieComment::get_account_size(&comment, true);
+
+    invoke_signed(
+        &system_instruction::create_account(
+            commenter.key,
+            pda_reply.key,
+            rent.minimum_balance(account_len),
+            account_len.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            commenter.clone(),
+            pda_reply.clone(),
+            system_program.clone()
+        ],
+        &[
+            &[
+                parent_comment.key.as_ref(),
+                counter_data.counter.to_be_bytes().as_ref(),
+                &[pda_reply.bump]
+            ]
+        ],
+    )?;
+
+    msg!("Created Reply Account");
+
+    let reply_data = MovieComment {
+        discriminator: MovieComment::DISCRIMINATOR,
+        is_initialized: true,
+        reviewer: review_data.comment_anchor,
+        commenter: *commenter.key,
+        comment,
+        count: counter_data.counter,
+        edit_count: 0,
+        last_edited_slot: 0,
+        is_deleted: false,
+        parent_comment: Some(*parent_comment.key),
+        depth,
+    };
+
+    reply_data.serialize(
+        &mut &mut pda_reply
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    msg!("Reply Count: {}", counter_data.counter);
+    counter_data.counter += 1;
+    counter_data.serialize(
+        &mut &mut pda_reply_counter
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    Ok(())
+}
+
+// Edits a comment's text, or with `None` deletes it. A deleted comment stays
+// as a tombstone at its index, shrunk to an empty comment with the freed rent
+// refunded to the commenter.
+pub fn update_comment(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    comment: Option<String>,
+) -> ProgramResult {
+    match &comment {
+        Some(comment) => {
+            msg!("Updating Comment...");
+            msg!("Comment: {}", comment);
+        },
+        None => msg!("Deleting Comment..."),
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let commenter = Signer::new(next_account_info(account_info_iter)?)?;
+    let pda_comment = next_account_info(account_info_iter)?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+
+    let mut comment_data = ProgramOwned::<MovieComment>::new(pda_comment, program_id)?.data;
+
+    if comment_data.commenter != *commenter.key {
+        msg!("Only the commenter can change a comment");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    if comment_data.is_deleted {
+        msg!("Comment has been deleted");
+        return Err(ReviewError::CommentDeleted.into());
+    }
+
+    comment_data.is_deleted = comment.is_none();
+    comment_data.comment = comment.unwrap_or_default();
+    comment_data.edit_count += 1;
+    comment_data.last_edited_slot = Clock::get()?.slot;
+
+    let new_len = MovieComment::get_account_size(
+        &comment_data.comment,
+        comment_data.parent_comment.is_some(),
+    );
+    resize_account(pda_comment, &commenter, &system_program, new_len)?;
+
+    comment_data.serialize(
+        &mut &mut pda_comment
+        .data
+        .borrow_mut()[..]
+    )?;
+    msg!("Comment edited {} times", comment_data.edit_count);
+
+    Ok(())
+}
+
+// Casts or changes a vote on a review, or with `None` withdraws it. Votes and
+// the tally are keyed by the review's comment anchor, so they follow a review
+// that is moved to another movie.
+pub fn vote_on_review(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    helpful: Option<bool>,
+) -> ProgramResult {
+    match helpful {
+        Some(helpful) => msg!("Voting on review, helpful: {}", helpful),
+        None => msg!("Withdrawing vote..."),
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    let voter = Signer::new(next_account_info(account_info_iter)?)?;
+    let pda_review = next_account_info(account_info_iter)?;
+    let pda_vote = next_account_info(account_info_iter)?;
+    let pda_tally = next_account_info(account_info_iter)?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+
+    let comment_anchor = match helpful {
+        Some(_) => {
+            let review_data = ProgramOwned::<MovieAccountState>::new(pda_review, program_id)?.data;
+            Pda::new(pda_review, find_review_address(program_id, &review_data.reviewer, review_data.movie_id))?;
+
+            if review_data.reviewer == *voter.key {
+                msg!("Reviewers cannot vote on their own review");
+                return Err(ReviewError::CannotVoteOnOwnReview.into());
+            }
+
+            review_data.comment_anchor
+        },
+        // The vote names the review's anchor, so it can still be withdrawn
+        // after the review is deleted
+        None => {
+            if pda_vote.data_is_empty() {
+                msg!("No vote to withdraw");
+                return Err(ReviewError::VoteNotFound.into());
+            }
+
+            ProgramOwned::<MovieVote>::new(pda_vote, program_id)?.data.review
+        },
+    };
+
+    let pda_vote = Pda::new(pda_vote, find_vote_address(program_id, &comment_anchor, voter.key))?;
+    let pda_tally = Pda::new(pda_tally, find_vote_tally_address(program_id, &comment_anchor))?;
+
+    let previous_vote = if pda_vote.data_is_empty() {
+        None
+    } else {
+        Some(ProgramOwned::<MovieVote>::new(&pda_vote, program_id)?.data.helpful)
+    };
+
+    // Deleting the review closed its tally, so there is nothing to count
+    // the withdrawal against
+    if helpful.is_none() && pda_tally.data_is_empty() {
+        close_account(&pda_vote, &voter)?;
+        msg!("Vote withdrawn from a deleted review");
+        return Ok(());
+    }
+
+    let rent = Rent::get()?;
+
+    // The tally is created by the review's first vote
+    let mut tally_data = if pda_tally.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                voter.key,
+                pda_tally.key,
+                rent.minimum_balance(MovieVoteTally::SIZE),
+                MovieVoteTally::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                voter.clone(),
+                pda_tally.clone(),
+                system_program.clone(),
+            ],
+            &[&[comment_anchor.as_ref(), b"votes", &[pda_tally.bump]]],
+        )?;
+        msg!("Created vote tally: {}", pda_tally.key);
+
+        MovieVoteTally {
+            discriminator: MovieVoteTally::DISCRIMINATOR,
+            is_initialized: true,
+            helpful: 0,
+            unhelpful: 0,
+        }
+    } else {
+        ProgramOwned::<MovieVoteTally>::new(&pda_tally, program_id)?.data
+    };
+
+    if let Some(previous) = previous_vote {
+        tally_data.remove_vote(previous);
+    }
+
+    match helpful {
+        Some(helpful) => {
+            if previous_vote.is_none() {
+                invoke_signed(
+                    &system_instruction::create_account(
+                        voter.key,
+                        pda_vote.key,
+                        rent.minimum_balance(MovieVote::SIZE),
+                        MovieVote::SIZE.try_into().unwrap(),
+                        program_id,
+                    ),
+                    &[
+                        voter.clone(),
+                        pda_vote.clone(),
+                        system_program.clone(),
+                    ],
+                    &[&[b"vote", comment_anchor.as_ref(), voter.key.as_ref(), &[pda_vote.bump]]],
+                )?;
+            }
+
+            let vote_data = MovieVote {
+                discriminator: MovieVote::DISCRIMINATOR,
+                is_initialized: true,
+                voter: *voter.key,
+                review: comment_anchor,
+                helpful,
+            };
+            vote_data.serialize(
+                &mut &mut pda_vote
+                .data
+                .borrow_mut()[..]
+            )?;
+
+            tally_data.add_vote(helpful);
+        },
+        None => close_account(&pda_vote, &voter)?,
+    }
+
+    tally_data.serialize(
+        &mut &mut pda_tally
+        .data
+        .borrow_mut()[..]
+    )?;
+    msg!("Helpful: {}, unhelpful: {}", tally_data.helpful, tally_data.unhelpful);
+
+    Ok(())
+}
+
+// Logged in place of a reward when an instruction comes without the reward
+// accounts, in a fixed format so indexers can pick it out
+fn log_reward_skipped(action: &str, user: &Pubkey) {
+    msg!("RewardSkipped: action={} user={}", action, user);
+}
+
+// Mints up to `amount` reward tokens, in base units, into the user's
+// associated token account, signed by the mint authority PDA. The user's
+// reward ledger caps it by the config's limits. The ledger and the token
+// account are created by the user's first reward, with `user` paying the rent.
+fn mint_reward<'info>(
+    program_id: &Pubkey,
+    rewards: &RewardAccounts<'_, 'info>,
+    reward_limits: &RewardLimits,
+    user: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+) -> ProgramResult {
+    if amount == 0 {
+        msg!("No reward for this action");
+        return Ok(());
+    }
+
+    let ledger = &rewards.ledger;
+    let mut ledger_data = if ledger.data_is_empty() {
+        let rent = Rent::get()?;
+        invoke_signed(
+            &system_instruction::create_account(
+                user.key,
+                ledger.key,
+                rent.minimum_balance(RewardLedger::SIZE),
+                RewardLedger::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                user.clone(),
+                AccountInfo::clone(ledger),
+                system_program.clone(),
+            ],
+            &[&[b"reward_ledger", user.key.as_ref(), &[ledger.bump]]],
+        )?;
+        msg!("Created reward ledger: {}", ledger.key);
+
+        RewardLedger {
+            discriminator: RewardLedger::DISCRIMINATOR,
+            is_initialized: true,
+            user: *user.key,
+            window_start: 0,
+            window_minted: 0,
+            last_reward_slot: 0,
+        }
+    } else {
+        ProgramOwned::<RewardLedger>::new(ledger, program_id)?.data
+    };
+
+    let slot = Clock::get()?.slot;
+    let amount = ledger_data.grant(reward_limits, slot, amount);
+    ledger_data.serialize(
+        &mut &mut ledger
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    if amount == 0 {
+        msg!("Reward limit reached, nothing minted");
+        return Ok(());
+    }
+
+    // First-time users don't have a token account for the mint yet. This
+    // builder, unlike the deprecated one at the crate root, lists no rent
+    // sysvar: the ATA program reads rent with Rent::get
+    if rewards.user_ata.data_is_empty() {
+        invoke(
+            &create_associated_token_account(user.key, user.key, rewards.token_mint.key),
+            &[
+                user.clone(),
+                rewards.user_ata.clone(),
+                user.clone(),
+                rewards.token_mint.clone(),
+                system_program.clone(),
+                rewards.token_program.clone(),
+                rewards.associated_token_program.clone(),
+            ],
+        )?;
+        msg!("Created associated token account: {}", rewards.user_ata.key);
+    }
+
+    msg!("Minting {} base units to User associated token account", amount);
+    invoke_signed(
+        // Instruction
+        &spl_token::instruction::mint_to(
+            rewards.token_program.key,
+            rewards.token_mint.key,
+            rewards.user_ata.key,
+            rewards.mint_auth.key,
+            &[],
+            amount,
+        )?, // ? unwraps and returns the error if there is one
+        // Account_infos
+        &[
+            rewards.token_mint.clone(),
+            rewards.user_ata.clone(),
+            rewards.mint_auth.clone(),
+        ],
+        // Seeds
+        &[&[b"token_auth", &[rewards.mint_auth.bump]]],
+    )?;
+
+    Ok(())
+}
+
+pub fn delete_movie_review(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    msg!("Deleting movie review...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let reviewer = Signer::new(next_account_info(account_info_iter)?)?;
+    let pda_review = next_account_info(account_info_iter)?;
+    let pda_counter = next_account_info(account_info_iter)?;
+    // Where the rent from the closed accounts is sent, picked by the reviewer
+    let destination = next_account_info(account_info_iter)?;
+    let pda_aggregate = next_account_info(account_info_iter)?;
+    let pda_tally = next_account_info(account_info_iter)?;
+
+    let account_data = ProgramOwned::<MovieAccountState>::new(pda_review, program_id)?.data;
+
+    if account_data.reviewer != *reviewer.key {
+        msg!("Only the reviewer can delete a review");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    Pda::new(pda_review, find_review_address(program_id, reviewer.key, account_data.movie_id))?;
+
+    let counter_data = ProgramOwned::<MovieCommentCounter>::new(pda_counter, program_id)?.data;
+    Pda::new(pda_counter, find_comment_counter_address(program_id, &account_data.comment_anchor))?;
+    // Closed with the review if anyone has voted on it
+    Pda::new(pda_tally, find_vote_tally_address(program_id, &account_data.comment_anchor))?;
+    if !pda_tally.data_is_empty() {
+        ProgramOwned::<MovieVoteTally>::new(pda_tally, program_id)?;
+    }
+
+    if [pda_review.key, pda_counter.key, pda_tally.key].contains(&destination.key) {
+        msg!("Destination cannot be one of the closed accounts");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    // Its comments would be left behind with no review to resolve to
+    if counter_data.counter > 0 {
+        msg!("Review has {} comments and cannot be deleted", counter_data.counter);
+        return Err(ReviewError::ReviewHasComments.into());
+    }
+
+    let mut aggregate_data = ProgramOwned::<MovieAggregate>::new(pda_aggregate, program_id)?.data;
+    Pda::new(pda_aggregate, find_aggregate_address(program_id, account_data.movie_id))?;
+    aggregate_data.remove_rating(account_data.rating);
+    aggregate_data.serialize(
+        &mut &mut pda_aggregate
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    close_account(pda_review, destination)?;
+    close_account(pda_counter, destination)?;
+    if !pda_tally.data_is_empty() {
+        close_account(pda_tally, destination)?;
+        msg!("Vote tally closed");
+    }
+
+    msg!("Review deleted");
+
+    Ok(())
+}
+
+// Registers a movie under the next id from the registry. The title index is
+// created alongside it, so registering the same title and year twice fails.
+// Only the config admin can register movies, so the catalogue is curated.
+pub fn register_movie(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    title: String,
+    year: u16,
+    imdb_id: String,
+) -> ProgramResult {
+    msg!("Registering movie...");
+
+    // Stored with its whitespace collapsed, matched on its normalized form
+    let title = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    msg!("Title: {}", title);
+    msg!("Year: {}", year);
+
+    if title.is_empty() {
+        msg!("Title cannot be empty");
+        return Err(ReviewError::InvalidDataLength.into());
+    }
+
+    let account_len = Movie::get_account_size(&title, &imdb_id);
+    if account_len > Movie::MAX_SIZE {
+        msg!("Data length is larger than {} bytes", Movie::MAX_SIZE);
+        return Err(ReviewError::InvalidDataLength.into());
+    }
+
+    let account_info_iter = &mut accounts.iter();
+
+    // Pays for the movie and the title index
+    let admin = Signer::new(next_account_info(account_info_iter)?)?;
+    let config_data = load_config(program_id, next_account_info(account_info_iter)?)?;
+    let pda_registry = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_movie_registry_address(program_id),
+    )?;
+    let pda_movie = next_account_info(account_info_iter)?;
+    let pda_title = Pda::new(
+        next_account_info(account_info_iter)?,
+        find_movie_title_address(program_id, &title, year),
+    )?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+
+    if config_data.admin != *admin.key {
+        msg!("Only the config admin can register movies");
+        return Err(ReviewError::NotConfigAdmin.into());
+    }
+
+    if !pda_title.data_is_empty() {
+        msg!("Movie is already registered");
+        return Err(ReviewError::MovieAlreadyRegistered.into());
+    }
+
+    let rent = Rent::get()?;
+
+    // The registry is created by the first registration
+    let mut registry_data = if pda_registry.data_is_empty() {
+        invoke_signed(
+            &system_instruction::create_account(
+                admin.key,
+                pda_registry.key,
+                rent.minimum_balance(MovieRegistry::SIZE),
+                MovieRegistry::SIZE.try_into().unwrap(),
+                program_id,
+            ),
+            &[
+                admin.clone(),
+                pda_registry.clone(),
+                system_program.clone(),
+            ],
+            &[&[b"movie_registry", &[pda_registry.bump]]],
+        )?;
+        msg!("Created movie registry: {}", pda_registry.key);
+
+        MovieRegistry {
+            discriminator: MovieRegistry::DISCRIMINATOR,
+            is_initialized: true,
+            movie_count: 0,
+        }
+    } else {
+        ProgramOwned::<MovieRegistry>::new(&pda_registry, program_id)?.data
+    };
+
+    let movie_id = registry_data.movie_count;
+    let pda_movie = Pda::new(pda_movie, find_movie_address(program_id, movie_id))?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            admin.key,
+            pda_movie.key,
+            rent.minimum_balance(account_len),
+            account_len.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            admin.clone(),
+            pda_movie.clone(),
+            system_program.clone(),
+        ],
+        &[&[b"movie", movie_id.to_be_bytes().as_ref(), &[pda_movie.bump]]],
+    )?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            admin.key,
+            pda_title.key,
+            rent.minimum_balance(MovieTitleIndex::SIZE),
+            MovieTitleIndex::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            admin.clone(),
+            pda_title.clone(),
+            system_program.clone(),
+        ],
+        &[
+            &[
+                b"movie_title",
+                hash_title(&title).as_ref(),
+                year.to_be_bytes().as_ref(),
+                &[pda_title.bump]
+            ]
+        ],
+    )?;
+
+    let movie_data = Movie {
+        discriminator: Movie::DISCRIMINATOR,
+        is_initialized: true,
+        id: movie_id,
+        title,
+        year,
+        imdb_id,
+    };
+    movie_data.serialize(
+        &mut &mut pda_movie
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    let title_data = MovieTitleIndex {
+        discriminator: MovieTitleIndex::DISCRIMINATOR,
+        is_initialized: true,
+        movie_id,
+    };
+    title_data.serialize(
+        &mut &mut pda_title
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    registry_data.movie_count += 1;
+    registry_data.serialize(
+        &mut &mut pda_registry
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    msg!("Registered movie {}", movie_id);
+
+    Ok(())
+}
+
+// Moves a review written by the first deployed program from its title-seeded
+// address to the one for `movie_id`, and counts it in that movie's aggregate.
+// The old address becomes the review's comment anchor, which its counter is
+// already seeded by; the counter is rewritten in the current layout with its
+// count kept and pointed at the new address.
+pub fn migrate_review(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    movie_id: u64,
+) -> ProgramResult {
+    msg!("Migrating movie review to movie: {}", movie_id);
+
+    let account_info_iter = &mut accounts.iter();
+
+    let reviewer = Signer::new(next_account_info(account_info_iter)?)?;
+    let legacy_pda_review = next_account_info(account_info_iter)?;
+    let pda_counter = next_account_info(account_info_iter)?;
+    let pda_review = next_account_info(account_info_iter)?;
+    let pda_aggregate = next_account_info(account_info_iter)?;
+    let pda_movie = next_account_info(account_info_iter)?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+
+    // Legacy reviews predate the 8 byte tags, so they are read in their own
+    // layout and identified by their address
+    check_program_owned(legacy_pda_review, program_id)?;
+    let legacy_data = LegacyMovieAccountState::load(&legacy_pda_review.data.borrow())?;
+    if !legacy_data.is_initialized() {
+        msg!("Account {} is not initialized", legacy_pda_review.key);
+        return Err(ReviewError::UninitializedAccount.into());
+    }
+
+    if legacy_data.reviewer != *reviewer.key {
+        msg!("Only the reviewer can migrate a review");
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    Pda::new(legacy_pda_review, find_legacy_review_address(program_id, reviewer.key, &legacy_data.title)?)?;
+
+    // Checked before it is counted in the aggregate, which indexes its
+    // histogram by it
+    if !(1..=5).contains(&legacy_data.rating) {
+        msg!("Legacy review has a rating of {}", legacy_data.rating);
+        return Err(ReviewError::InvalidRating.into());
+    }
+
+    let pda_counter = Pda::new(pda_counter, find_comment_counter_address(program_id, legacy_pda_review.key))?;
+    check_program_owned(&pda_counter, program_id)?;
+    let legacy_counter_data = LegacyMovieCommentCounter::load(&pda_counter.data.borrow())?;
+
+    let pda_review = Pda::new(pda_review, find_review_address(program_id, reviewer.key, movie_id))?;
+    let pda_aggregate = Pda::new(pda_aggregate, find_aggregate_address(program_id, movie_id))?;
+
+    let movie_data = ProgramOwned::<Movie>::new(pda_movie, program_id)?.data;
+    Pda::new(pda_movie, find_movie_address(program_id, movie_id))?;
+    msg!("From title: {}", legacy_data.title);
+    msg!("To title: {}", movie_data.title);
+
+    let account_data = MovieAccountState {
+        discriminator: MovieAccountState::DISCRIMINATOR,
+        is_initialized: true,
+        reviewer: legacy_data.reviewer,
+        rating: legacy_data.rating,
+        movie_id,
+        description: legacy_data.description,
+        comment_anchor: *legacy_pda_review.key,
+    };
+
+    let account_len = MovieAccountState::get_account_size(&account_data.description);
+    let rent = Rent::get()?;
+
+    invoke_signed(
+        &system_instruction::create_account(
+            reviewer.key,
+            pda_review.key,
+            rent.minimum_balance(account_len),
+            account_len.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            reviewer.clone(),
+            pda_review.clone(),
+            system_program.clone(),
+        ],
+        &[
+            &[
+                reviewer.key.as_ref(),
+                movie_id.to_be_bytes().as_ref(),
+                &[pda_review.bump]
+            ]
+        ]
+    )?;
+
+    account_data.serialize(
+        &mut &mut pda_review
+        .data
+        .borrow_mut()[..]
+    )?;
+    msg!("PDA created: {}", pda_review.key);
+
+    let mut aggregate_data = load_or_create_aggregate(
+        program_id,
+        &pda_aggregate,
+        &reviewer,
+        &system_program,
+        movie_id,
+    )?;
+    aggregate_data.add_rating(account_data.rating);
+    aggregate_data.serialize(
+        &mut &mut pda_aggregate
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    let counter_data = MovieCommentCounter {
+        discriminator: MovieCommentCounter::DISCRIMINATOR,
+        is_initialized: true,
+        counter: legacy_counter_data.counter,
+        review: *pda_review.key,
+    };
+    msg!("comment count: {}", counter_data.counter);
+
+    // The current layout is longer, the reviewer pays the extra rent
+    resize_account(&pda_counter, &reviewer, &system_program, MovieCommentCounter::SIZE)?;
+    counter_data.serialize(
+        &mut &mut pda_counter
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    close_account(legacy_pda_review, &reviewer)?;
+    msg!("Review migrated from {}", legacy_pda_review.key);
+
+    Ok(())
+}
+
+// Moves every lamport out of `account` into `destination` and zeroes its data,
+// the runtime then removes the account at the end of the transaction
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> ProgramResult {
+    let lamports = account.lamports();
+
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ProgramError::InvalidArgument)?;
+    **account.try_borrow_mut_lamports()? = 0;
+
+    account.try_borrow_mut_data()?.fill(0);
+
+    Ok(())
+}
+
+// At a high level, next steps are:
+// 1. Iterate through list of accounts to extract them
+// 2. Derive token mint PDA
+// 3. Validate all of the important accounts passed in:
+//      1. Token mint account
+//      2. Mint authority account
+//      3. System program
+//      4. Token program
+//      5. Sysvar rent - the rent calculation account
+// 4. Calculate rent for the mint account
+// 5. Create the token mint PDA
+// 6. Initialize the mint account
+
+pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> ProgramResult {
+    let account_info_iter = &mut accounts.iter();
+
+    // The order of accounts is not arbitrary, the client will send them in this order
+    // Whoever sent in the transaction
+    let initializer = Signer::new(next_account_info(account_info_iter)?)?;
+    // Token mint PDA - derived on the client, the seed is just "token_mint"
+    let token_mint = Pda::new(next_account_info(account_info_iter)?, find_mint_address(program_id))?;
+    // Token mint authority, the seed is just "token_auth"
+    let mint_auth = Pda::new(next_account_info(account_info_iter)?, find_mint_authority_address(program_id))?;
+    // System program to create a new account
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+    // Solana Token program address
+    let token_program = TokenProgram::new(next_account_info(account_info_iter)?)?;
+    // System account to calcuate the rent
+    let sysvar_rent = RentSysvar::new(next_account_info(account_info_iter)?)?;
+
+    msg!("Token mint: {:?}", token_mint.key);
+    msg!("Mint authority: {:?}", mint_auth.key);
+
+    // Calculate the rent
+    let rent = Rent::get()?;
+    // We know the size of a mint account is 82 (remember it lol)
+    let rent_lamports = rent.minimum_balance(82);
+
+    // Create the token mint PDA
+    invoke_signed(
+        &system_instruction::create_account(
             initializer.key,
             token_mint.key,
             rent_lamports,
@@ -587,7 +1637,7 @@ pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> P
             system_program.clone(),
         ],
         // Seeds for our token mint account
-        &[&[b"token_mint", &[mint_bump]]],
+        &[&[b"token_mint", &[token_mint.bump]]],
     )?;
 
     msg!("Created token mint account");
@@ -604,10 +1654,1153 @@ pub fn initialize_token_mint(program_id: &Pubkey, accounts: &[AccountInfo]) -> P
         // Which accounts we're reading from or writing to
         &[token_mint.clone(), sysvar_rent.clone(), mint_auth.clone()],
         // The seeds for our token mint PDA
-        &[&[b"token_mint", &[mint_bump]]],
+        &[&[b"token_mint", &[token_mint.bump]]],
     )?;
 
     msg!("Initialized token mint");
 
     Ok(())
-}
\ No newline at end of file
+}
+
+// Creates the config. Only the program's upgrade authority can, so nobody
+// can claim the admin between the deploy and the deployer's own call
+pub fn initialize_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    review_reward: RewardSetting,
+    comment_reward: RewardSetting,
+    reward_limits: RewardLimits,
+    self_comment_policy: SelfCommentPolicy,
+) -> ProgramResult {
+    msg!("Initializing config...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let admin = Signer::new(next_account_info(account_info_iter)?)?;
+    let pda_config = Pda::new(next_account_info(account_info_iter)?, find_config_address(program_id))?;
+    let program_data = next_account_info(account_info_iter)?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+
+    check_upgrade_authority(program_id, program_data, admin.key)?;
+
+    let rent = Rent::get()?;
+    invoke_signed(
+        &system_instruction::create_account(
+            admin.key,
+            pda_config.key,
+            rent.minimum_balance(Config::SIZE),
+            Config::SIZE.try_into().unwrap(),
+            program_id,
+        ),
+        &[
+            admin.clone(),
+            pda_config.clone(),
+            system_program.clone(),
+        ],
+        &[&[b"config", &[pda_config.bump]]],
+    )?;
+
+    let config_data = Config {
+        discriminator: Config::DISCRIMINATOR,
+        is_initialized: true,
+        admin: *admin.key,
+        review_reward,
+        comment_reward,
+        reward_limits,
+        self_comment_policy,
+    };
+    config_data.serialize(
+        &mut &mut pda_config
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    msg!("Config admin: {}", admin.key);
+
+    Ok(())
+}
+
+// Replaces every setting in the config, signed by its current admin
+pub fn update_config(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    new_admin: Pubkey,
+    review_reward: RewardSetting,
+    comment_reward: RewardSetting,
+    reward_limits: RewardLimits,
+    self_comment_policy: SelfCommentPolicy,
+) -> ProgramResult {
+    msg!("Updating config...");
+
+    let account_info_iter = &mut accounts.iter();
+
+    let admin = Signer::new(next_account_info(account_info_iter)?)?;
+    let pda_config = next_account_info(account_info_iter)?;
+    let mut config_data = load_config(program_id, pda_config)?;
+
+    if config_data.admin != *admin.key {
+        msg!("Only the config admin can update it");
+        return Err(ReviewError::NotConfigAdmin.into());
+    }
+
+    config_data.admin = new_admin;
+    config_data.review_reward = review_reward;
+    config_data.comment_reward = comment_reward;
+    config_data.reward_limits = reward_limits;
+    config_data.self_comment_policy = self_comment_policy;
+
+    config_data.serialize(
+        &mut &mut pda_config
+        .data
+        .borrow_mut()[..]
+    )?;
+
+    msg!("Config admin: {}", config_data.admin);
+    msg!("Review reward: {:?}", config_data.review_reward);
+    msg!("Comment reward: {:?}", config_data.comment_reward);
+    msg!("Reward limits: {:?}", config_data.reward_limits);
+    msg!("Self-comment policy: {:?}", config_data.self_comment_policy);
+
+    Ok(())
+}
+
+// Attaches Metaplex metadata to the reward mint so wallets can show its name,
+// symbol and logo. The mint authority PDA signs as both the mint and update
+// authority, so only this program can change the metadata later.
+pub fn create_mint_metadata(
+    program_id: &Pubkey,
+    accounts: &[AccountInfo],
+    name: String,
+    symbol: String,
+    uri: String,
+) -> ProgramResult {
+    msg!("Creating token metadata...");
+    msg!("Name: {}", name);
+    msg!("Symbol: {}", symbol);
+    msg!("Uri: {}", uri);
+
+    let account_info_iter = &mut accounts.iter();
+
+    // The config admin, who also pays for the metadata account
+    let admin = Signer::new(next_account_info(account_info_iter)?)?;
+    let config_data = load_config(program_id, next_account_info(account_info_iter)?)?;
+    let token_mint = Pda::new(next_account_info(account_info_iter)?, find_mint_address(program_id))?;
+    let metadata = Pda::new(next_account_info(account_info_iter)?, find_metadata_address(token_mint.key))?;
+    let mint_auth = Pda::new(next_account_info(account_info_iter)?, find_mint_authority_address(program_id))?;
+    let system_program = SystemProgram::new(next_account_info(account_info_iter)?)?;
+    let sysvar_rent = RentSysvar::new(next_account_info(account_info_iter)?)?;
+    let metadata_program = TokenMetadataProgram::new(next_account_info(account_info_iter)?)?;
+
+    if config_data.admin != *admin.key {
+        msg!("Only the config admin can create token metadata");
+        return Err(ReviewError::NotConfigAdmin.into());
+    }
+
+    invoke_signed(
+        &create_metadata_account_v3(
+            metadata.key,
+            token_mint.key,
+            mint_auth.key,
+            admin.key,
+            mint_auth.key,
+            name,
+            symbol,
+            uri,
+        ),
+        &[
+            metadata.clone(),
+            token_mint.clone(),
+            mint_auth.clone(),
+            admin.clone(),
+            system_program.clone(),
+            sysvar_rent.clone(),
+            metadata_program.clone(),
+        ],
+        &[&[b"token_auth", &[mint_auth.bump]]],
+    )?;
+
+    msg!("Created token metadata: {}", metadata.key);
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use solana_program::{
+        bpf_loader_upgradeable,
+        entrypoint::{deserialize, MAX_PERMITTED_DATA_INCREASE, NON_DUP_MARKER, SUCCESS},
+        instruction::Instruction,
+        program_option::COption,
+        program_pack::Pack,
+        program_stubs::{set_syscall_stubs, SyscallStubs},
+        system_instruction::SystemError,
+        system_program,
+    };
+    use spl_associated_token_account::get_associated_token_address;
+    use spl_token::error::TokenError;
+    use spl_token::instruction::TokenInstruction;
+    use spl_token::state::{Account as TokenAccount, AccountState, Mint};
+    use std::collections::HashMap;
+    use std::sync::Once;
+
+    use crate::audit::check_comments;
+    use crate::instruction;
+    use crate::pda::{find_program_data_address, find_reward_ledger_address};
+
+    const PROGRAM_ID: Pubkey = Pubkey::new_from_array([0x4d; 32]);
+    const TEST_SLOT: u64 = 1_000;
+
+    // Stands in for the runtime: serves the rent and clock sysvars and runs
+    // the System Program, Associated Token Program and SPL Token instructions
+    // the processor calls
+    struct TestSyscalls;
+
+    impl SyscallStubs for TestSyscalls {
+        fn sol_get_rent_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Rent) = Rent::default() };
+            SUCCESS
+        }
+
+        fn sol_get_clock_sysvar(&self, var_addr: *mut u8) -> u64 {
+            unsafe { *(var_addr as *mut Clock) = Clock { slot: TEST_SLOT, ..Clock::default() } };
+            SUCCESS
+        }
+
+        fn sol_invoke_signed(
+            &self,
+            instruction: &Instruction,
+            account_infos: &[AccountInfo],
+            signers_seeds: &[&[&[u8]]],
+        ) -> ProgramResult {
+            // The runtime fails with MissingAccount when a meta has no
+            // matching account info, ProgramError has no such variant
+            let accounts = instruction
+                .accounts
+                .iter()
+                .map(|meta| account_infos.iter().find(|info| *info.key == meta.pubkey))
+                .collect::<Option<Vec<_>>>()
+                .ok_or(ProgramError::NotEnoughAccountKeys)?;
+
+            // A PDA signs by its seeds
+            for meta in instruction.accounts.iter().filter(|meta| meta.is_signer) {
+                let signed = account_infos.iter().any(|info| info.key == &meta.pubkey && info.is_signer)
+                    || signers_seeds.iter().any(|seeds| {
+                        Pubkey::create_program_address(seeds, &PROGRAM_ID) == Ok(meta.pubkey)
+                    });
+                if !signed {
+                    return Err(ProgramError::MissingRequiredSignature);
+                }
+            }
+
+            if instruction.program_id == system_program::id() {
+                invoke_system_program(&instruction.data, &accounts)
+            } else if instruction.program_id == spl_associated_token_account::id() {
+                invoke_associated_token_program(&accounts)
+            } else if instruction.program_id == spl_token::id() {
+                invoke_token_program(&instruction.data, &accounts)
+            } else {
+                Err(ProgramError::IncorrectProgramId)
+            }
+        }
+    }
+
+    fn invoke_system_program(data: &[u8], accounts: &[&AccountInfo]) -> ProgramResult {
+        let (tag, data) = data.split_at(4);
+        let read_u64 = |offset: usize| u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap());
+
+        match u32::from_le_bytes(tag.try_into().unwrap()) {
+            // CreateAccount { lamports, space, owner }
+            0 => create_account(accounts[0], accounts[1], read_u64(0), read_u64(8) as usize, &Pubkey::new(&data[16..48])),
+            // Transfer { lamports }
+            2 => {
+                debit(accounts[0], read_u64(0))?;
+                **accounts[1].try_borrow_mut_lamports()? += read_u64(0);
+                Ok(())
+            }
+            _ => Err(ProgramError::InvalidInstructionData),
+        }
+    }
+
+    // Create { funder, token account, wallet, mint, system, token program },
+    // the token account is created and initialized for the wallet
+    fn invoke_associated_token_program(accounts: &[&AccountInfo]) -> ProgramResult {
+        let [funder, token_account, wallet, mint, ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        if *token_account.key != get_associated_token_address(wallet.key, mint.key) {
+            return Err(ProgramError::InvalidSeeds);
+        }
+        if mint.owner != &spl_token::id() {
+            return Err(ProgramError::IllegalOwner);
+        }
+
+        let lamports = Rent::default().minimum_balance(TokenAccount::LEN);
+        create_account(funder, token_account, lamports, TokenAccount::LEN, &spl_token::id())?;
+        let state = TokenAccount {
+            mint: *mint.key,
+            owner: *wallet.key,
+            state: AccountState::Initialized,
+            ..TokenAccount::default()
+        };
+        TokenAccount::pack(state, &mut token_account.data.borrow_mut())
+    }
+
+    // Only MintTo { mint, token account, mint authority }
+    fn invoke_token_program(data: &[u8], accounts: &[&AccountInfo]) -> ProgramResult {
+        let TokenInstruction::MintTo { amount } = TokenInstruction::unpack(data)? else {
+            return Err(ProgramError::InvalidInstructionData);
+        };
+        let [mint, token_account, authority, ..] = accounts else {
+            return Err(ProgramError::NotEnoughAccountKeys);
+        };
+        if mint.owner != &spl_token::id() || token_account.owner != &spl_token::id() {
+            return Err(ProgramError::IncorrectProgramId);
+        }
+
+        let mut mint_state = Mint::unpack(&mint.data.borrow())?;
+        let mut account_state = TokenAccount::unpack(&token_account.data.borrow())?;
+        if mint_state.mint_authority != COption::Some(*authority.key) {
+            return Err(TokenError::OwnerMismatch.into());
+        }
+        if account_state.mint != *mint.key {
+            return Err(TokenError::MintMismatch.into());
+        }
+
+        mint_state.supply = mint_state.supply.checked_add(amount).ok_or(TokenError::Overflow)?;
+        account_state.amount += amount;
+        Mint::pack(mint_state, &mut mint.data.borrow_mut())?;
+        TokenAccount::pack(account_state, &mut token_account.data.borrow_mut())
+    }
+
+    fn create_account(from: &AccountInfo, to: &AccountInfo, lamports: u64, space: usize, owner: &Pubkey) -> ProgramResult {
+        if to.lamports() > 0 || !to.data_is_empty() {
+            return Err(ProgramError::Custom(SystemError::AccountAlreadyInUse as u32));
+        }
+        debit(from, lamports)?;
+        **to.try_borrow_mut_lamports()? = lamports;
+        to.realloc(space, true)?;
+        to.assign(owner);
+        Ok(())
+    }
+
+    fn debit(account: &AccountInfo, lamports: u64) -> ProgramResult {
+        let balance = account.lamports();
+        **account.try_borrow_mut_lamports()? = balance
+            .checked_sub(lamports)
+            .ok_or(ProgramError::Custom(SystemError::ResultWithNegativeLamports as u32))?;
+        Ok(())
+    }
+
+    #[derive(Clone)]
+    struct TestAccount {
+        lamports: u64,
+        owner: Pubkey,
+        data: Vec<u8>,
+    }
+
+    impl TestAccount {
+        fn program_owned(data: Vec<u8>) -> Self {
+            TestAccount {
+                lamports: Rent::default().minimum_balance(data.len()),
+                owner: PROGRAM_ID,
+                data,
+            }
+        }
+    }
+
+    // Accounts by address. An instruction either commits all of its changes
+    // or, when it fails, none of them
+    #[derive(Default)]
+    struct TestBank {
+        accounts: HashMap<Pubkey, TestAccount>,
+    }
+
+    impl TestBank {
+        fn new() -> Self {
+            static STUBS: Once = Once::new();
+            STUBS.call_once(|| {
+                set_syscall_stubs(Box::new(TestSyscalls));
+            });
+
+            TestBank::default()
+        }
+
+        fn wallet(&mut self) -> Pubkey {
+            let key = Pubkey::new_unique();
+            self.accounts.insert(key, TestAccount {
+                lamports: 10_000_000_000,
+                owner: system_program::id(),
+                data: vec![],
+            });
+            key
+        }
+
+        fn account(&self, key: &Pubkey) -> Option<&TestAccount> {
+            self.accounts.get(key)
+        }
+
+        fn load<T: ProgramAccount>(&self, key: &Pubkey) -> T {
+            T::load(&self.account(key).expect("account exists").data).unwrap()
+        }
+
+        fn process(&mut self, instruction: &Instruction) -> ProgramResult {
+            let mut input = self.serialize_input(instruction);
+            let (program_id, account_infos, data) = unsafe { deserialize(input.as_mut_ptr() as *mut u8) };
+
+            process_instruction(program_id, &account_infos, data)?;
+
+            for info in &account_infos {
+                if info.lamports() == 0 {
+                    self.accounts.remove(info.key);
+                } else {
+                    self.accounts.insert(*info.key, TestAccount {
+                        lamports: info.lamports(),
+                        owner: *info.owner,
+                        data: info.data.borrow().to_vec(),
+                    });
+                }
+            }
+
+            Ok(())
+        }
+
+        // Lays the accounts out the way the runtime hands them to the
+        // entrypoint, room to grow included, so realloc works as on chain
+        fn serialize_input(&self, instruction: &Instruction) -> Vec<u64> {
+            let metas = &instruction.accounts;
+            let mut bytes = (metas.len() as u64).to_le_bytes().to_vec();
+
+            for (index, meta) in metas.iter().enumerate() {
+                if let Some(first) = metas[..index].iter().position(|other| other.pubkey == meta.pubkey) {
+                    bytes.push(first as u8);
+                    bytes.extend([0; 7]);
+                    continue;
+                }
+
+                let same_key = || metas.iter().filter(|other| other.pubkey == meta.pubkey);
+                let account = self.accounts.get(&meta.pubkey).cloned().unwrap_or(TestAccount {
+                    lamports: 0,
+                    owner: system_program::id(),
+                    data: vec![],
+                });
+
+                bytes.push(NON_DUP_MARKER);
+                bytes.push(same_key().any(|other| other.is_signer) as u8);
+                bytes.push(same_key().any(|other| other.is_writable) as u8);
+                bytes.push(0);
+                bytes.extend([0; 4]);
+                bytes.extend(meta.pubkey.as_ref());
+                bytes.extend(account.owner.as_ref());
+                bytes.extend(account.lamports.to_le_bytes());
+                bytes.extend((account.data.len() as u64).to_le_bytes());
+                bytes.extend(&account.data);
+                bytes.resize(bytes.len() + MAX_PERMITTED_DATA_INCREASE, 0);
+                bytes.resize(bytes.len().next_multiple_of(8), 0);
+                bytes.extend(0u64.to_le_bytes());
+            }
+
+            bytes.extend((instruction.data.len() as u64).to_le_bytes());
+            bytes.extend(&instruction.data);
+            bytes.extend(instruction.program_id.as_ref());
+
+            let mut input = vec![0u64; bytes.len().div_ceil(8)];
+            unsafe {
+                std::ptr::copy_nonoverlapping(bytes.as_ptr(), input.as_mut_ptr() as *mut u8, bytes.len());
+            }
+            input
+        }
+    }
+
+    // The loader's ProgramData header for a program deployed with `authority`
+    // as its upgrade authority, or made immutable with None
+    fn deploy(bank: &mut TestBank, authority: Option<Pubkey>) {
+        let mut data = 3u32.to_le_bytes().to_vec();
+        data.extend(TEST_SLOT.to_le_bytes());
+        data.extend(authority.try_to_vec().unwrap());
+        // The loader sizes the header for an authority either way
+        data.resize(bpf_loader_upgradeable::UpgradeableLoaderState::size_of_programdata_metadata(), 0);
+        bank.accounts.insert(find_program_data_address(&PROGRAM_ID).0, TestAccount {
+            lamports: Rent::default().minimum_balance(data.len()),
+            owner: bpf_loader_upgradeable::id(),
+            data,
+        });
+    }
+
+    fn initialize_config_ix(admin: &Pubkey, self_comment_policy: SelfCommentPolicy) -> Instruction {
+        instruction::initialize_config(
+            &PROGRAM_ID,
+            admin,
+            RewardSetting { amount: 10, enabled: true },
+            RewardSetting { amount: 5, enabled: true },
+            RewardLimits { cap_per_window: 0, window_slots: 0, cooldown_slots: 0 },
+            self_comment_policy,
+        )
+    }
+
+    fn initialize_config(bank: &mut TestBank, self_comment_policy: SelfCommentPolicy) -> Pubkey {
+        let admin = bank.wallet();
+        deploy(bank, Some(admin));
+        bank.process(&initialize_config_ix(&admin, self_comment_policy)).unwrap();
+        admin
+    }
+
+    fn register_movie(bank: &mut TestBank, admin: &Pubkey, movie_id: u64, title: &str) {
+        let ix = instruction::register_movie(&PROGRAM_ID, admin, movie_id, title.to_string(), 1995, String::new());
+        bank.process(&ix).unwrap();
+    }
+
+    fn add_review(bank: &mut TestBank, reviewer: &Pubkey, review_count: u64, movie_id: u64, rating: u8) -> ProgramResult {
+        let ix = instruction::add_movie_review(&PROGRAM_ID, reviewer, review_count, movie_id, rating, "Review".to_string());
+        bank.process(&instruction::without_rewards(ix))
+    }
+
+    fn review_address(reviewer: &Pubkey, movie_id: u64) -> Pubkey {
+        find_review_address(&PROGRAM_ID, reviewer, movie_id).0
+    }
+
+    #[test]
+    fn review_can_be_rewritten_after_moving_to_another_movie() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        let reviewer = bank.wallet();
+        register_movie(&mut bank, &admin, 0, "Heat");
+        register_movie(&mut bank, &admin, 1, "Ronin");
+
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+        let first_anchor = bank.load::<MovieAccountState>(&review_address(&reviewer, 0)).comment_anchor;
+
+        let ix = instruction::move_movie_review(&PROGRAM_ID, &reviewer, 0, &first_anchor, 1, 4, "Review".to_string());
+        bank.process(&ix).unwrap();
+        assert!(bank.account(&review_address(&reviewer, 0)).is_none());
+
+        // The moved review keeps its anchor, the new one at the old address gets its own
+        add_review(&mut bank, &reviewer, 1, 0, 2).unwrap();
+        let moved = bank.load::<MovieAccountState>(&review_address(&reviewer, 1));
+        let rewritten = bank.load::<MovieAccountState>(&review_address(&reviewer, 0));
+        assert_eq!(moved.comment_anchor, first_anchor);
+        assert_ne!(rewritten.comment_anchor, first_anchor);
+        assert_eq!(bank.load::<ReviewerState>(&find_reviewer_address(&PROGRAM_ID, &reviewer).0).review_count, 2);
+
+        let (first_counter, _) = find_comment_counter_address(&PROGRAM_ID, &first_anchor);
+        assert_eq!(bank.load::<MovieCommentCounter>(&first_counter).review, review_address(&reviewer, 1));
+        let (new_counter, _) = find_comment_counter_address(&PROGRAM_ID, &rewritten.comment_anchor);
+        let counter = bank.load::<MovieCommentCounter>(&new_counter);
+        assert_eq!(counter.review, review_address(&reviewer, 0));
+        assert_eq!(counter.counter, 0);
+
+        // Both reviews take comments
+        let commenter = bank.wallet();
+        comment(&mut bank, &commenter, &reviewer, 0).unwrap();
+        comment(&mut bank, &commenter, &reviewer, 1).unwrap();
+    }
+
+    #[test]
+    fn review_count_must_match_the_reviewer_account() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        let reviewer = bank.wallet();
+        register_movie(&mut bank, &admin, 0, "Heat");
+        register_movie(&mut bank, &admin, 1, "Ronin");
+
+        assert_eq!(add_review(&mut bank, &reviewer, 1, 0, 4), Err(ReviewError::InvalidPDA.into()));
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+        assert_eq!(add_review(&mut bank, &reviewer, 0, 1, 4), Err(ReviewError::InvalidPDA.into()));
+        add_review(&mut bank, &reviewer, 1, 1, 4).unwrap();
+    }
+
+    // Comments at the review's next index, reward group included
+    fn comment_ix(bank: &TestBank, commenter: &Pubkey, reviewer: &Pubkey, movie_id: u64) -> Instruction {
+        let pda_review = review_address(reviewer, movie_id);
+        let review = bank.load::<MovieAccountState>(&pda_review);
+        let count = bank
+            .load::<MovieCommentCounter>(&find_comment_counter_address(&PROGRAM_ID, &review.comment_anchor).0)
+            .counter;
+        instruction::add_comment(
+            &PROGRAM_ID,
+            commenter,
+            &pda_review,
+            &review.comment_anchor,
+            count,
+            "Agreed".to_string(),
+        )
+    }
+
+    fn comment(bank: &mut TestBank, commenter: &Pubkey, reviewer: &Pubkey, movie_id: u64) -> ProgramResult {
+        let ix = comment_ix(bank, commenter, reviewer, movie_id);
+        bank.process(&instruction::without_rewards(ix))
+    }
+
+    // A review by a fresh reviewer with one comment on it, returns the
+    // commenter, the review's address and the comment's
+    fn setup_comment(bank: &mut TestBank) -> (Pubkey, Pubkey, Pubkey) {
+        let admin = initialize_config(bank, SelfCommentPolicy::NoReward);
+        register_movie(bank, &admin, 0, "Heat");
+        let reviewer = bank.wallet();
+        add_review(bank, &reviewer, 0, 0, 4).unwrap();
+        let commenter = bank.wallet();
+        comment(bank, &commenter, &reviewer, 0).unwrap();
+
+        let pda_review = review_address(&reviewer, 0);
+        let anchor = bank.load::<MovieAccountState>(&pda_review).comment_anchor;
+        (commenter, pda_review, find_comment_address(&PROGRAM_ID, &anchor, 0).0)
+    }
+
+    #[test]
+    fn edit_counts_edits_and_resizes_the_comment() {
+        let mut bank = TestBank::new();
+        let (commenter, _, pda_comment) = setup_comment(&mut bank);
+        let balance = bank.account(&commenter).unwrap().lamports;
+        let rent = bank.account(&pda_comment).unwrap().lamports;
+
+        let text = "Agreed, the diner scene is the best in the film".to_string();
+        bank.process(&instruction::update_comment(&PROGRAM_ID, &commenter, &pda_comment, text.clone())).unwrap();
+
+        let comment = bank.load::<MovieComment>(&pda_comment);
+        assert_eq!((comment.comment.as_str(), comment.count), (text.as_str(), 0));
+        assert_eq!((comment.edit_count, comment.last_edited_slot), (1, TEST_SLOT));
+        assert!(!comment.is_deleted);
+        let account = bank.account(&pda_comment).unwrap();
+        let size = MovieComment::get_account_size(&text, false);
+        assert_eq!(account.data.len(), size);
+        assert_eq!(account.lamports, Rent::default().minimum_balance(size));
+        assert_eq!(bank.account(&commenter).unwrap().lamports, balance - (account.lamports - rent));
+
+        // Shrinking refunds the rent that is no longer needed
+        bank.process(&instruction::update_comment(&PROGRAM_ID, &commenter, &pda_comment, "Agreed".to_string())).unwrap();
+        assert_eq!(bank.load::<MovieComment>(&pda_comment).edit_count, 2);
+        assert_eq!(bank.account(&pda_comment).unwrap().lamports, rent);
+        assert_eq!(bank.account(&commenter).unwrap().lamports, balance);
+    }
+
+    #[test]
+    fn delete_leaves_an_empty_tombstone_at_the_same_index() {
+        let mut bank = TestBank::new();
+        let (commenter, _, pda_comment) = setup_comment(&mut bank);
+        let balance = bank.account(&commenter).unwrap().lamports;
+        let rent = bank.account(&pda_comment).unwrap().lamports;
+
+        bank.process(&instruction::delete_comment(&PROGRAM_ID, &commenter, &pda_comment)).unwrap();
+
+        let comment = bank.load::<MovieComment>(&pda_comment);
+        assert!(comment.is_deleted);
+        assert_eq!((comment.comment.as_str(), comment.count, comment.commenter), ("", 0, commenter));
+        assert_eq!((comment.edit_count, comment.last_edited_slot), (1, TEST_SLOT));
+        let account = bank.account(&pda_comment).unwrap();
+        let size = MovieComment::get_account_size("", false);
+        assert_eq!(account.data.len(), size);
+        assert_eq!(account.lamports, Rent::default().minimum_balance(size));
+        assert_eq!(bank.account(&commenter).unwrap().lamports, balance + rent - account.lamports);
+
+        // A tombstone can be neither edited nor deleted again
+        assert_eq!(
+            bank.process(&instruction::update_comment(&PROGRAM_ID, &commenter, &pda_comment, "Back".to_string())),
+            Err(ReviewError::CommentDeleted.into())
+        );
+        assert_eq!(
+            bank.process(&instruction::delete_comment(&PROGRAM_ID, &commenter, &pda_comment)),
+            Err(ReviewError::CommentDeleted.into())
+        );
+    }
+
+    #[test]
+    fn only_the_commenter_changes_a_comment() {
+        let mut bank = TestBank::new();
+        let (_, _, pda_comment) = setup_comment(&mut bank);
+        let other = bank.wallet();
+
+        assert_eq!(
+            bank.process(&instruction::update_comment(&PROGRAM_ID, &other, &pda_comment, "Mine now".to_string())),
+            Err(ReviewError::IncorrectAccountError.into())
+        );
+        assert_eq!(
+            bank.process(&instruction::delete_comment(&PROGRAM_ID, &other, &pda_comment)),
+            Err(ReviewError::IncorrectAccountError.into())
+        );
+        assert_eq!(bank.load::<MovieComment>(&pda_comment).edit_count, 0);
+    }
+
+    // Replies to `parent` at the next free index of its reply counter
+    fn reply(bank: &mut TestBank, commenter: &Pubkey, pda_review: &Pubkey, parent: &Pubkey) -> Result<Pubkey, ProgramError> {
+        let (pda_reply_counter, _) = find_reply_counter_address(&PROGRAM_ID, parent);
+        let count = match bank.account(&pda_reply_counter) {
+            Some(_) => bank.load::<MovieReplyCounter>(&pda_reply_counter).counter,
+            None => 0,
+        };
+        let ix = instruction::reply_to_comment(&PROGRAM_ID, commenter, pda_review, parent, count, "Reply".to_string());
+        bank.process(&ix)?;
+        Ok(find_reply_address(&PROGRAM_ID, parent, count).0)
+    }
+
+    #[test]
+    fn first_reply_creates_the_reply_counter() {
+        let mut bank = TestBank::new();
+        let (_, pda_review, pda_comment) = setup_comment(&mut bank);
+        let (pda_reply_counter, _) = find_reply_counter_address(&PROGRAM_ID, &pda_comment);
+        assert!(bank.account(&pda_reply_counter).is_none());
+
+        let replier = bank.wallet();
+        let first = reply(&mut bank, &replier, &pda_review, &pda_comment).unwrap();
+        assert_eq!(bank.load::<MovieReplyCounter>(&pda_reply_counter).counter, 1);
+        let account = bank.account(&pda_reply_counter).unwrap();
+        assert_eq!(account.lamports, Rent::default().minimum_balance(MovieReplyCounter::SIZE));
+
+        let second = reply(&mut bank, &replier, &pda_review, &pda_comment).unwrap();
+        assert_eq!(bank.load::<MovieReplyCounter>(&pda_reply_counter).counter, 2);
+
+        let anchor = bank.load::<MovieAccountState>(&pda_review).comment_anchor;
+        for (index, pda_reply) in [first, second].iter().enumerate() {
+            let reply = bank.load::<MovieComment>(pda_reply);
+            assert_eq!((reply.reviewer, reply.commenter, reply.count), (anchor, replier, index as u64));
+            assert_eq!((reply.parent_comment, reply.depth), (Some(pda_comment), 1));
+        }
+    }
+
+    #[test]
+    fn replies_stop_at_the_max_depth() {
+        let mut bank = TestBank::new();
+        let (_, pda_review, mut parent) = setup_comment(&mut bank);
+        let replier = bank.wallet();
+
+        for depth in 1..=MovieComment::MAX_DEPTH {
+            parent = reply(&mut bank, &replier, &pda_review, &parent).unwrap();
+            assert_eq!(bank.load::<MovieComment>(&parent).depth, depth);
+        }
+
+        assert_eq!(
+            reply(&mut bank, &replier, &pda_review, &parent),
+            Err(ReviewError::ReplyTooDeep.into())
+        );
+        assert!(bank.account(&find_reply_counter_address(&PROGRAM_ID, &parent).0).is_none());
+    }
+
+    #[test]
+    fn reply_parent_must_be_on_the_same_review() {
+        let mut bank = TestBank::new();
+        let (_, _, pda_comment) = setup_comment(&mut bank);
+        let other_reviewer = bank.wallet();
+        add_review(&mut bank, &other_reviewer, 0, 0, 2).unwrap();
+        let replier = bank.wallet();
+
+        assert_eq!(
+            reply(&mut bank, &replier, &review_address(&other_reviewer, 0), &pda_comment),
+            Err(ReviewError::ParentCommentMismatch.into())
+        );
+    }
+
+    #[test]
+    fn reply_parent_must_not_be_deleted() {
+        let mut bank = TestBank::new();
+        let (commenter, pda_review, pda_comment) = setup_comment(&mut bank);
+        bank.process(&instruction::delete_comment(&PROGRAM_ID, &commenter, &pda_comment)).unwrap();
+        let replier = bank.wallet();
+
+        assert_eq!(
+            reply(&mut bank, &replier, &pda_review, &pda_comment),
+            Err(ReviewError::CommentDeleted.into())
+        );
+    }
+
+    #[test]
+    fn only_the_upgrade_authority_initializes_the_config() {
+        let mut bank = TestBank::new();
+        let deployer = bank.wallet();
+        let front_runner = bank.wallet();
+        deploy(&mut bank, Some(deployer));
+
+        let ix = initialize_config_ix(&front_runner, SelfCommentPolicy::NoReward);
+        assert_eq!(bank.process(&ix), Err(ReviewError::NotUpgradeAuthority.into()));
+
+        let ix = initialize_config_ix(&deployer, SelfCommentPolicy::NoReward);
+        bank.process(&ix).unwrap();
+        assert_eq!(bank.load::<Config>(&find_config_address(&PROGRAM_ID).0).admin, deployer);
+    }
+
+    #[test]
+    fn immutable_program_cannot_initialize_the_config() {
+        let mut bank = TestBank::new();
+        let admin = bank.wallet();
+        deploy(&mut bank, None);
+
+        let ix = initialize_config_ix(&admin, SelfCommentPolicy::NoReward);
+        assert_eq!(bank.process(&ix), Err(ReviewError::NotUpgradeAuthority.into()));
+    }
+
+    #[test]
+    fn initialize_config_rejects_program_data_not_owned_by_the_loader() {
+        let mut bank = TestBank::new();
+        let admin = bank.wallet();
+        deploy(&mut bank, Some(admin));
+        bank.accounts.get_mut(&find_program_data_address(&PROGRAM_ID).0).unwrap().owner = PROGRAM_ID;
+
+        let ix = initialize_config_ix(&admin, SelfCommentPolicy::NoReward);
+        assert_eq!(bank.process(&ix), Err(ProgramError::IllegalOwner));
+    }
+
+    #[test]
+    fn only_the_config_admin_registers_movies() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        let other = bank.wallet();
+
+        let ix = instruction::register_movie(&PROGRAM_ID, &other, 0, "Heat".to_string(), 1995, String::new());
+        assert_eq!(bank.process(&ix), Err(ReviewError::NotConfigAdmin.into()));
+
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let movie = bank.load::<Movie>(&find_movie_address(&PROGRAM_ID, 0).0);
+        assert_eq!((movie.id, movie.title.as_str(), movie.year), (0, "Heat", 1995));
+    }
+
+    #[test]
+    fn self_comment_policy_applies_without_the_reward_accounts() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::Reject);
+        let reviewer = bank.wallet();
+        register_movie(&mut bank, &admin, 0, "Heat");
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+
+        assert_eq!(comment(&mut bank, &reviewer, &reviewer, 0), Err(ReviewError::SelfCommentRejected.into()));
+
+        let commenter = bank.wallet();
+        comment(&mut bank, &commenter, &reviewer, 0).unwrap();
+    }
+
+    #[test]
+    fn comments_pass_the_sequence_check() {
+        let mut bank = TestBank::new();
+        let (commenter, pda_review, pda_comment) = setup_comment(&mut bank);
+        let reviewer = bank.load::<MovieAccountState>(&pda_review).reviewer;
+        comment(&mut bank, &commenter, &reviewer, 0).unwrap();
+        bank.process(&instruction::delete_comment(&PROGRAM_ID, &commenter, &pda_comment)).unwrap();
+        comment(&mut bank, &commenter, &reviewer, 0).unwrap();
+
+        let anchor = bank.load::<MovieAccountState>(&pda_review).comment_anchor;
+        let counter = bank.load::<MovieCommentCounter>(&find_comment_counter_address(&PROGRAM_ID, &anchor).0);
+        assert_eq!(counter.counter, 3);
+        let issues = check_comments(&PROGRAM_ID, &anchor, counter.counter, |address| {
+            bank.account(address).map(|account| account.data.clone())
+        });
+        assert_eq!(issues, vec![]);
+    }
+
+    #[test]
+    fn add_comment_requires_the_config() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::Reject);
+        let reviewer = bank.wallet();
+        register_movie(&mut bank, &admin, 0, "Heat");
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+
+        let pda_review = review_address(&reviewer, 0);
+        let anchor = bank.load::<MovieAccountState>(&pda_review).comment_anchor;
+        let mut ix = instruction::without_rewards(instruction::add_comment(
+            &PROGRAM_ID,
+            &reviewer,
+            &pda_review,
+            &anchor,
+            0,
+            "Agreed".to_string(),
+        ));
+        ix.accounts.pop();
+        assert_eq!(bank.process(&ix), Err(ProgramError::NotEnoughAccountKeys));
+    }
+
+    #[test]
+    fn deleted_review_takes_its_votes_with_it() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        let reviewer = bank.wallet();
+        let voter = bank.wallet();
+        register_movie(&mut bank, &admin, 0, "Heat");
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+
+        let pda_review = review_address(&reviewer, 0);
+        let anchor = bank.load::<MovieAccountState>(&pda_review).comment_anchor;
+        bank.process(&instruction::vote_on_review(&PROGRAM_ID, &voter, &pda_review, &anchor, true)).unwrap();
+
+        let ix = instruction::delete_movie_review(&PROGRAM_ID, &reviewer, 0, &anchor, &reviewer);
+        bank.process(&ix).unwrap();
+        let (pda_tally, _) = find_vote_tally_address(&PROGRAM_ID, &anchor);
+        assert!(bank.account(&pda_tally).is_none());
+
+        // A new review at the same address starts with no votes
+        add_review(&mut bank, &reviewer, 1, 0, 2).unwrap();
+        let new_anchor = bank.load::<MovieAccountState>(&pda_review).comment_anchor;
+        assert_ne!(new_anchor, anchor);
+        bank.process(&instruction::vote_on_review(&PROGRAM_ID, &voter, &pda_review, &new_anchor, false)).unwrap();
+        let tally = bank.load::<MovieVoteTally>(&find_vote_tally_address(&PROGRAM_ID, &new_anchor).0);
+        assert_eq!((tally.helpful, tally.unhelpful), (0, 1));
+
+        // The vote on the deleted review can still be withdrawn for its rent
+        let (old_vote, _) = find_vote_address(&PROGRAM_ID, &anchor, &voter);
+        let vote_rent = bank.account(&old_vote).unwrap().lamports;
+        let balance = bank.account(&voter).unwrap().lamports;
+        bank.process(&instruction::withdraw_vote(&PROGRAM_ID, &voter, &pda_review, &anchor)).unwrap();
+        assert!(bank.account(&old_vote).is_none());
+        assert_eq!(bank.account(&voter).unwrap().lamports, balance + vote_rent);
+
+        // The new review's tally is untouched
+        let tally = bank.load::<MovieVoteTally>(&find_vote_tally_address(&PROGRAM_ID, &new_anchor).0);
+        assert_eq!((tally.helpful, tally.unhelpful), (0, 1));
+        assert_eq!(
+            bank.process(&instruction::withdraw_vote(&PROGRAM_ID, &voter, &pda_review, &anchor)),
+            Err(ReviewError::VoteNotFound.into())
+        );
+    }
+
+    // A review and comment counter as the first deployed program left them:
+    // a 1000 byte review with an empty string discriminator and a 20 byte
+    // counter that only ever had its count written
+    fn setup_legacy_review(bank: &mut TestBank, reviewer: &Pubkey, title: &str, rating: u8, comments: u64) -> Pubkey {
+        let (pda_review, _) = find_legacy_review_address(&PROGRAM_ID, reviewer, title).unwrap();
+        let legacy = LegacyMovieAccountState {
+            discriminator: String::new(),
+            is_initialized: true,
+            reviewer: *reviewer,
+            rating,
+            title: title.to_string(),
+            description: "Still holds up".to_string(),
+        };
+        let mut review = legacy.try_to_vec().unwrap();
+        review.resize(1000, 0);
+        let counter = LegacyMovieCommentCounter {
+            discriminator: String::new(),
+            is_initialized: false,
+            counter: comments,
+        };
+        let (pda_counter, _) = find_comment_counter_address(&PROGRAM_ID, &pda_review);
+        bank.accounts.insert(pda_review, TestAccount::program_owned(review));
+        bank.accounts.insert(pda_counter, TestAccount::program_owned(counter.try_to_vec().unwrap()));
+        pda_review
+    }
+
+    #[test]
+    fn migrate_moves_a_legacy_review_into_the_aggregate() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let reviewer = bank.wallet();
+        let legacy_review = setup_legacy_review(&mut bank, &reviewer, "heat", 4, 2);
+        let (pda_counter, _) = find_comment_counter_address(&PROGRAM_ID, &legacy_review);
+        let balance = bank.account(&reviewer).unwrap().lamports;
+
+        let ix = instruction::migrate_review(&PROGRAM_ID, &reviewer, "heat", 0).unwrap();
+        bank.process(&ix).unwrap();
+
+        assert!(bank.account(&legacy_review).is_none());
+        let pda_review = review_address(&reviewer, 0);
+        let review = bank.load::<MovieAccountState>(&pda_review);
+        assert_eq!((review.reviewer, review.rating, review.movie_id), (reviewer, 4, 0));
+        assert_eq!((review.description.as_str(), review.comment_anchor), ("Still holds up", legacy_review));
+        let aggregate = bank.load::<MovieAggregate>(&find_aggregate_address(&PROGRAM_ID, 0).0);
+        assert_eq!(aggregate.histogram, [0, 0, 0, 1, 0]);
+
+        // The counter keeps its count in the current layout
+        let counter = bank.load::<MovieCommentCounter>(&pda_counter);
+        assert_eq!((counter.counter, counter.review), (2, pda_review));
+        let account = bank.account(&pda_counter).unwrap();
+        assert_eq!(account.data.len(), MovieCommentCounter::SIZE);
+        assert_eq!(account.lamports, Rent::default().minimum_balance(MovieCommentCounter::SIZE));
+
+        // The 1000 byte review's rent more than covers the new accounts
+        assert!(bank.account(&reviewer).unwrap().lamports > balance);
+
+        // New comments continue after the legacy ones
+        let commenter = bank.wallet();
+        let ix = instruction::add_comment(&PROGRAM_ID, &commenter, &pda_review, &legacy_review, 2, "Agreed".to_string());
+        bank.process(&instruction::without_rewards(ix)).unwrap();
+        assert_eq!(bank.load::<MovieCommentCounter>(&pda_counter).counter, 3);
+    }
+
+    #[test]
+    fn migrate_rejects_rating_out_of_range() {
+        for rating in [0, 6, 255] {
+            let mut bank = TestBank::new();
+            let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+            register_movie(&mut bank, &admin, 0, "Heat");
+            let reviewer = bank.wallet();
+            setup_legacy_review(&mut bank, &reviewer, "heat", rating, 0);
+
+            let ix = instruction::migrate_review(&PROGRAM_ID, &reviewer, "heat", 0).unwrap();
+            assert_eq!(bank.process(&ix), Err(ReviewError::InvalidRating.into()));
+        }
+    }
+
+    #[test]
+    fn migrate_is_only_for_the_reviewer() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let reviewer = bank.wallet();
+        let legacy_review = setup_legacy_review(&mut bank, &reviewer, "heat", 4, 0);
+
+        // Someone else naming the reviewer's legacy review
+        let other = bank.wallet();
+        let mut ix = instruction::migrate_review(&PROGRAM_ID, &other, "heat", 0).unwrap();
+        ix.accounts[1].pubkey = legacy_review;
+        ix.accounts[2].pubkey = find_comment_counter_address(&PROGRAM_ID, &legacy_review).0;
+        assert_eq!(bank.process(&ix), Err(ReviewError::IncorrectAccountError.into()));
+    }
+
+    #[test]
+    fn migrate_builder_rejects_title_too_long_to_be_a_seed() {
+        let reviewer = Pubkey::new_unique();
+        let title = "The Assassination of Jesse James by the Coward Robert Ford";
+
+        assert_eq!(
+            instruction::migrate_review(&PROGRAM_ID, &reviewer, title, 0),
+            Err(ReviewError::InvalidPDA.into())
+        );
+    }
+
+    // The reward mint as initialize_mint leaves it, owned by the token
+    // program with the program's PDA as its mint authority
+    fn create_reward_mint(bank: &mut TestBank) {
+        let mint = Mint {
+            mint_authority: COption::Some(find_mint_authority_address(&PROGRAM_ID).0),
+            decimals: 9,
+            is_initialized: true,
+            ..Mint::default()
+        };
+        let mut data = vec![0; Mint::LEN];
+        Mint::pack(mint, &mut data).unwrap();
+        bank.accounts.insert(find_mint_address(&PROGRAM_ID).0, TestAccount {
+            lamports: Rent::default().minimum_balance(Mint::LEN),
+            owner: spl_token::id(),
+            data,
+        });
+    }
+
+    fn token_account(bank: &TestBank, wallet: &Pubkey) -> TokenAccount {
+        let address = get_associated_token_address(wallet, &find_mint_address(&PROGRAM_ID).0);
+        let account = bank.account(&address).expect("token account exists");
+        assert_eq!(account.owner, spl_token::id());
+        TokenAccount::unpack(&account.data).unwrap()
+    }
+
+    #[test]
+    fn first_reward_creates_the_token_account_then_mints_into_it() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        create_reward_mint(&mut bank);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        register_movie(&mut bank, &admin, 1, "Ronin");
+        let reviewer = bank.wallet();
+
+        bank.process(&review_ix(&reviewer, 0, 0)).unwrap();
+        let account = token_account(&bank, &reviewer);
+        assert_eq!((account.mint, account.owner, account.amount), (find_mint_address(&PROGRAM_ID).0, reviewer, 10));
+
+        bank.process(&review_ix(&reviewer, 1, 1)).unwrap();
+        assert_eq!(token_account(&bank, &reviewer).amount, 20);
+        let mint = Mint::unpack(&bank.account(&find_mint_address(&PROGRAM_ID).0).unwrap().data).unwrap();
+        assert_eq!(mint.supply, 20);
+    }
+
+    fn review_ix(reviewer: &Pubkey, review_count: u64, movie_id: u64) -> Instruction {
+        instruction::add_movie_review(&PROGRAM_ID, reviewer, review_count, movie_id, 4, "Review".to_string())
+    }
+
+    fn has_token_account(bank: &TestBank, wallet: &Pubkey) -> bool {
+        bank.account(&get_associated_token_address(wallet, &find_mint_address(&PROGRAM_ID).0)).is_some()
+    }
+
+    #[test]
+    fn reward_group_is_checked_when_present() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        create_reward_mint(&mut bank);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let reviewer = bank.wallet();
+        let other = bank.wallet();
+        let len = review_ix(&reviewer, 0, 0).accounts.len();
+
+        // Reward group order: mint, mint authority, token account, token program, ledger, ATA program
+        let mut ix = review_ix(&reviewer, 0, 0);
+        ix.accounts[len - 4].pubkey = get_associated_token_address(&other, &find_mint_address(&PROGRAM_ID).0);
+        assert_eq!(bank.process(&ix), Err(ReviewError::IncorrectAccountError.into()));
+
+        let mut ix = review_ix(&reviewer, 0, 0);
+        ix.accounts[len - 2].pubkey = find_reward_ledger_address(&PROGRAM_ID, &other).0;
+        assert_eq!(bank.process(&ix), Err(ReviewError::InvalidPDA.into()));
+
+        let mut ix = review_ix(&reviewer, 0, 0);
+        ix.accounts.truncate(len - 1);
+        assert_eq!(bank.process(&ix), Err(ProgramError::NotEnoughAccountKeys));
+
+        assert!(bank.account(&review_address(&reviewer, 0)).is_none());
+        bank.process(&review_ix(&reviewer, 0, 0)).unwrap();
+        assert_eq!(token_account(&bank, &reviewer).amount, 10);
+    }
+
+    #[test]
+    fn comments_mint_the_comment_reward() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        create_reward_mint(&mut bank);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let reviewer = bank.wallet();
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+
+        let commenter = bank.wallet();
+        bank.process(&comment_ix(&bank, &commenter, &reviewer, 0)).unwrap();
+        assert_eq!(token_account(&bank, &commenter).amount, 5);
+        assert!(bank.account(&find_reward_ledger_address(&PROGRAM_ID, &commenter).0).is_some());
+    }
+
+    #[test]
+    fn disabled_rewards_mint_nothing() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::NoReward);
+        create_reward_mint(&mut bank);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let ix = instruction::update_config(
+            &PROGRAM_ID,
+            &admin,
+            admin,
+            RewardSetting { amount: 10, enabled: false },
+            RewardSetting { amount: 5, enabled: false },
+            RewardLimits { cap_per_window: 0, window_slots: 0, cooldown_slots: 0 },
+            SelfCommentPolicy::ReducedReward { amount: 2 },
+        );
+        bank.process(&ix).unwrap();
+
+        let reviewer = bank.wallet();
+        bank.process(&review_ix(&reviewer, 0, 0)).unwrap();
+        let commenter = bank.wallet();
+        bank.process(&comment_ix(&bank, &commenter, &reviewer, 0)).unwrap();
+        // The reduced self-comment reward is off along with the comment reward
+        bank.process(&comment_ix(&bank, &reviewer, &reviewer, 0)).unwrap();
+
+        assert!(!has_token_account(&bank, &reviewer));
+        assert!(!has_token_account(&bank, &commenter));
+        let mint = Mint::unpack(&bank.account(&find_mint_address(&PROGRAM_ID).0).unwrap().data).unwrap();
+        assert_eq!(mint.supply, 0);
+    }
+
+    #[test]
+    fn self_comments_mint_what_the_policy_pays() {
+        let mut bank = TestBank::new();
+        let admin = initialize_config(&mut bank, SelfCommentPolicy::ReducedReward { amount: 2 });
+        create_reward_mint(&mut bank);
+        register_movie(&mut bank, &admin, 0, "Heat");
+        let reviewer = bank.wallet();
+        add_review(&mut bank, &reviewer, 0, 0, 4).unwrap();
+
+        bank.process(&comment_ix(&bank, &reviewer, &reviewer, 0)).unwrap();
+        assert_eq!(token_account(&bank, &reviewer).amount, 2);
+
+        let ix = instruction::update_config(
+            &PROGRAM_ID,
+            &admin,
+            admin,
+            RewardSetting { amount: 10, enabled: true },
+            RewardSetting { amount: 5, enabled: true },
+            RewardLimits { cap_per_window: 0, window_slots: 0, cooldown_slots: 0 },
+            SelfCommentPolicy::NoReward,
+        );
+        bank.process(&ix).unwrap();
+        bank.process(&comment_ix(&bank, &reviewer, &reviewer, 0)).unwrap();
+        assert_eq!(token_account(&bank, &reviewer).amount, 2);
+    }
+}
@@ -1,10 +1,33 @@
 use borsh::{BorshSerialize, BorshDeserialize};
 use solana_program::{
+    borsh::try_from_slice_unchecked,
+    program_error::ProgramError,
     program_pack::{IsInitialized, Sealed}, pubkey::Pubkey
 };
 
+use crate::error::ReviewError;
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct MovieAccountState {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub reviewer: Pubkey,
+    pub rating: u8,
+    // Id of the registered Movie this review is about
+    pub movie_id: u64,
+    pub description: String,
+    // Address the comment counter and comments are seeded from. Derived from
+    // the reviewer's review count when the review is written, so no later
+    // review can land on it, and kept when the review is moved. Reviews from
+    // before that used the address they were first created at
+    pub comment_anchor: Pubkey,
+}
+
+// Review layout of the first deployed program, from before the movie registry
+// and the 8 byte tags, when reviews were seeded by their free-text title.
+// Only ever read from a legacy review address, by MigrateReview
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct LegacyMovieAccountState {
     pub discriminator: String,
     pub is_initialized: bool,
     pub reviewer: Pubkey,
@@ -13,21 +36,183 @@ pub struct MovieAccountState {
     pub description: String,
 }
 
+// Comment counter layout of the same program, seeded by the legacy review's
+// address. Its add_comment only ever wrote the count, so the discriminator
+// and is_initialized are whatever the zeroed account held
 #[derive(BorshSerialize, BorshDeserialize)]
-pub struct MovieCommentCounter {
+pub struct LegacyMovieCommentCounter {
     pub discriminator: String,
     pub is_initialized: bool,
     pub counter: u64,
 }
 
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieCommentCounter {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub counter: u64,
+    // Current address of the review, followed forward when it is moved so
+    // comments that name the review's original address still resolve
+    pub review: Pubkey,
+}
+
 #[derive(BorshSerialize, BorshDeserialize)]
 pub struct MovieComment {
-    pub discriminator: String,
+    pub discriminator: [u8; 8],
     pub is_initialized: bool,
     pub reviewer: Pubkey,
     pub commenter: Pubkey,
     pub comment: String,
     pub count: u64,
+    pub edit_count: u32,
+    // Slot of the last edit or delete, 0 if the comment was never changed
+    pub last_edited_slot: u64,
+    // A deleted comment keeps its account and index with the text cleared,
+    // so the indexes clients page through stay gap-free
+    pub is_deleted: bool,
+    // The comment this one replies to, None for a top level comment. Replies
+    // are seeded by their parent and their index among its replies
+    pub parent_comment: Option<Pubkey>,
+    // 0 for a top level comment, one more than the parent for a reply
+    pub depth: u8,
+}
+
+// One wallet's vote on one review, closed when the vote is withdrawn
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieVote {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub voter: Pubkey,
+    // Comment anchor of the review voted on, which stays put if it is moved
+    pub review: Pubkey,
+    pub helpful: bool,
+}
+
+// Helpful and unhelpful votes on one review, created by its first vote
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieVoteTally {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub helpful: u64,
+    pub unhelpful: u64,
+}
+
+// Program-wide settings, changed by the admin with UpdateConfig
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Config {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub admin: Pubkey,
+    pub review_reward: RewardSetting,
+    pub comment_reward: RewardSetting,
+    pub reward_limits: RewardLimits,
+    pub self_comment_policy: SelfCommentPolicy,
+}
+
+// Reward for one kind of action
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewardSetting {
+    // In the mint's base units, so it doesn't depend on its decimals
+    pub amount: u64,
+    pub enabled: bool,
+}
+
+// What happens when a reviewer comments on their own review
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfCommentPolicy {
+    // The comment is stored without a reward
+    NoReward,
+    // The comment earns `amount` base units instead of the comment reward,
+    // still subject to the comment reward being enabled
+    ReducedReward { amount: u64 },
+    // The comment is refused
+    Reject,
+}
+
+// How much one wallet can earn, shared by every rewarded action
+#[derive(BorshSerialize, BorshDeserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewardLimits {
+    // Most a wallet can be minted per window in base units, 0 for no cap
+    pub cap_per_window: u64,
+    // Length of a window in slots, windows start at multiples of it. 0 makes
+    // the cap a lifetime one
+    pub window_slots: u64,
+    // Slots after a reward before the wallet can earn another, 0 for none
+    pub cooldown_slots: u64,
+}
+
+// Rewards minted to one wallet, checked against the config's RewardLimits.
+// Created by the wallet's first reward
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct RewardLedger {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub user: Pubkey,
+    // First slot of the window window_minted counts
+    pub window_start: u64,
+    pub window_minted: u64,
+    // 0 until the first reward
+    pub last_reward_slot: u64,
+}
+
+// Number of reviews one wallet has written, created with its first review.
+// Never goes down, so each review gets a comment anchor of its own
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct ReviewerState {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub review_count: u64,
+}
+
+// Number of replies a comment has had, created on its first reply
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieReplyCounter {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub counter: u64,
+}
+
+// Running totals for every review of one movie, so the average rating is a
+// single account read
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieAggregate {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub review_count: u64,
+    pub rating_sum: u64,
+    // histogram[0] counts 1 star reviews, histogram[4] counts 5 stars
+    pub histogram: [u64; 5],
+}
+
+// A film reviews point at by id, so every spelling of its title ends up on
+// the same reviews and aggregate
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct Movie {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub id: u64,
+    // Whitespace collapsed, case kept as registered
+    pub title: String,
+    pub year: u16,
+    // Empty when unknown
+    pub imdb_id: String,
+}
+
+// Singleton that hands out movie ids in registration order
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieRegistry {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    // Also the id the next registered movie gets
+    pub movie_count: u64,
+}
+
+// Points a normalized title and year at the movie registered under them
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct MovieTitleIndex {
+    pub discriminator: [u8; 8],
+    pub is_initialized: bool,
+    pub movie_id: u64,
 }
 
 
@@ -37,6 +222,15 @@ pub struct MovieComment {
 // fixed size, so we need to implement it!
 impl Sealed for MovieAccountState {}
 impl Sealed for MovieCommentCounter {}
+impl Sealed for MovieAggregate {}
+impl Sealed for MovieRegistry {}
+impl Sealed for MovieTitleIndex {}
+impl Sealed for MovieReplyCounter {}
+impl Sealed for MovieVote {}
+impl Sealed for MovieVoteTally {}
+impl Sealed for Config {}
+impl Sealed for RewardLedger {}
+impl Sealed for ReviewerState {}
 
 impl IsInitialized for MovieAccountState {
     fn is_initialized(&self) -> bool {
@@ -56,38 +250,796 @@ impl IsInitialized for MovieComment {
     }
 }
 
+impl IsInitialized for MovieAggregate {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for LegacyMovieAccountState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for Movie {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for MovieRegistry {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for MovieTitleIndex {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for MovieReplyCounter {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for MovieVote {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for MovieVoteTally {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for Config {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for RewardLedger {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
+impl IsInitialized for ReviewerState {
+    fn is_initialized(&self) -> bool {
+        self.is_initialized
+    }
+}
+
 impl MovieAccountState {
-    pub const DISCRIMINATOR: &'static str = "review";
+    // Largest review the program will create or grow an account to
+    pub const MAX_SIZE: usize = 1000;
 
-    pub fn get_account_size(title: String, description: String) -> usize {
-        // 4 bytes to store the size of the subsequent dynamic data (string)
-        return (4 + MovieAccountState::DISCRIMINATOR.len())
+    // The one place the size of a review account is worked out. Every field
+    // of the struct must be counted here, in the same order it is serialized
+    pub fn get_account_size(description: &str) -> usize {
+        DISCRIMINATOR_LEN // 8 byte type tag
             + 1 // 1 byte for is_initialized (boolean)
+            + 32 // 32 bytes for the reviewer key
             + 1 // 1 byte for rating
+            + 8 // 8 bytes for the movie id (u64)
+            + (4 + description.len()) // 4 bytes to store the size of the subsequent dynamic data (string)
+            + 32 // 32 bytes for the comment anchor key
+    }
+}
+
+impl LegacyMovieAccountState {
+    pub const DISCRIMINATOR: &'static str = "review";
+
+    // Legacy reviews sit in fixed 1000 byte accounts, the zeros after the
+    // description are ignored. The old add_movie_review assigned DISCRIMINATOR
+    // to the description instead, so its reviews have an empty one
+    pub fn load(data: &[u8]) -> Result<Self, ProgramError> {
+        let review = try_from_slice_unchecked::<Self>(data).map_err(|_| ProgramError::InvalidAccountData)?;
+        if !review.discriminator.is_empty() && review.discriminator != Self::DISCRIMINATOR {
+            return Err(ReviewError::InvalidAccountKind.into());
+        }
+
+        Ok(review)
+    }
+}
+
+impl LegacyMovieCommentCounter {
+    pub fn load(data: &[u8]) -> Result<Self, ProgramError> {
+        try_from_slice_unchecked::<Self>(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
+
+impl Movie {
+    // Largest movie the program will register
+    pub const MAX_SIZE: usize = 1000;
+
+    pub fn get_account_size(title: &str, imdb_id: &str) -> usize {
+        DISCRIMINATOR_LEN // 8 byte type tag
+            + 1 // 1 byte for is_initialized (boolean)
+            + 8 // 8 bytes for the id (u64)
             + (4 + title.len()) // 4 bytes to store the size of the subsequent dynamic data (string)
-            + (4 + description.len() // same as above
-        );
+            + 2 // 2 bytes for the year (u16)
+            + (4 + imdb_id.len()) // same as the title
     }
 }
 
+impl MovieRegistry {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 8;  // 8 bytes for the movie count (u64)
+}
+
+impl MovieTitleIndex {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 8;  // 8 bytes for the movie id (u64)
+}
+
 impl MovieComment {
-    pub const DISCRIMINATOR: &'static str = "comment";
+    // Deepest a reply can be nested
+    pub const MAX_DEPTH: u8 = 5;
 
-    pub fn get_account_size(comment: String) -> usize {
-        // 4 bytes to store the size of the subsequent dynamic data (string)
-        return (4 + MovieComment::DISCRIMINATOR.len())
+    pub fn get_account_size(comment: &str, is_reply: bool) -> usize {
+        DISCRIMINATOR_LEN // 8 byte type tag
         + 1  // 1 byte for is_initialized (boolean)
         + 32  // 32 bytes for the movie review account key
         + 32  // 32 bytes for the commenter key size
         + (4 + comment.len())  // 4 bytes to store the size of the subsequent dynamic data (string)
+        + 8  // 8 bytes for the count (u64)
+        + 4  // 4 bytes for the edit count (u32)
+        + 8  // 8 bytes for the last edited slot (u64)
+        + 1  // 1 byte for is_deleted (boolean)
+        + 1 + if is_reply { 32 } else { 0 }  // 1 byte for the option tag, 32 more for the parent comment key
+        + 1  // 1 byte for the depth
+    }
+}
+
+impl Config {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 32  // 32 bytes for the admin key
+        + RewardSetting::SIZE  // review reward
+        + RewardSetting::SIZE  // comment reward
+        + RewardLimits::SIZE
+        + SelfCommentPolicy::MAX_SIZE;
+
+    // What a comment pays, before the commenter's reward limits
+    pub fn comment_payout(&self, is_self_comment: bool) -> u64 {
+        if !is_self_comment {
+            return self.comment_reward.payout();
+        }
+
+        match self.self_comment_policy {
+            SelfCommentPolicy::ReducedReward { amount } if self.comment_reward.enabled => amount,
+            _ => 0,
+        }
+    }
+}
+
+impl SelfCommentPolicy {
+    pub const MAX_SIZE: usize = 1  // 1 byte for the variant
+        + 8;  // 8 bytes for the reduced amount (u64)
+}
+
+impl RewardSetting {
+    pub const SIZE: usize = 8  // 8 bytes for the amount (u64)
+        + 1;  // 1 byte for enabled (boolean)
+
+    // What the action actually pays, nothing while it is disabled
+    pub fn payout(&self) -> u64 {
+        if self.enabled {
+            self.amount
+        } else {
+            0
+        }
+    }
+}
+
+impl RewardLimits {
+    pub const SIZE: usize = 8  // 8 bytes for the cap (u64)
+        + 8  // 8 bytes for the window length (u64)
+        + 8;  // 8 bytes for the cooldown (u64)
+}
+
+impl RewardLedger {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 32  // 32 bytes for the user key
+        + 8  // 8 bytes for the window start slot (u64)
+        + 8  // 8 bytes for the amount minted this window (u64)
+        + 8;  // 8 bytes for the last reward slot (u64)
+
+    // Records a reward of up to `requested` at `slot` and returns how much of
+    // it the limits allow, which is 0 during the cooldown or once the
+    // window's cap is reached
+    pub fn grant(&mut self, limits: &RewardLimits, slot: u64, requested: u64) -> u64 {
+        if limits.window_slots > 0 && slot >= self.window_start.saturating_add(limits.window_slots) {
+            self.window_start = slot - slot % limits.window_slots;
+            self.window_minted = 0;
+        }
+
+        if self.last_reward_slot > 0 && slot < self.last_reward_slot.saturating_add(limits.cooldown_slots) {
+            return 0;
+        }
+
+        let amount = if limits.cap_per_window > 0 {
+            requested.min(limits.cap_per_window.saturating_sub(self.window_minted))
+        } else {
+            requested
+        };
+
+        if amount > 0 {
+            self.window_minted = self.window_minted.saturating_add(amount);
+            self.last_reward_slot = slot;
+        }
+
+        amount
+    }
+}
+
+impl ReviewerState {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 8;  // 8 bytes for the review count (u64)
+}
+
+impl MovieReplyCounter {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
         + 8;  // 8 bytes for the count (u64)
+}
+
+impl MovieVote {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 32  // 32 bytes for the voter key
+        + 32  // 32 bytes for the review's comment anchor
+        + 1;  // 1 byte for helpful (boolean)
+}
+
+impl MovieVoteTally {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 8  // 8 bytes for the helpful count (u64)
+        + 8;  // 8 bytes for the unhelpful count (u64)
+
+    pub fn add_vote(&mut self, helpful: bool) {
+        if helpful {
+            self.helpful += 1;
+        } else {
+            self.unhelpful += 1;
+        }
+    }
+
+    pub fn remove_vote(&mut self, helpful: bool) {
+        if helpful {
+            self.helpful = self.helpful.saturating_sub(1);
+        } else {
+            self.unhelpful = self.unhelpful.saturating_sub(1);
+        }
     }
 }
 
 impl MovieCommentCounter {
-    pub const DISCRIMINATOR: &'static str = "counter";
-        // 4 bytes to store the size of the subsequent dynamic data (string)
-    pub const SIZE: usize = (4 + MovieCommentCounter::DISCRIMINATOR.len()) 
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
         + 1  // 1 byte for is_initialized (boolean)
-        + 8;  // 8 bytes for the count (u64)
+        + 8  // 8 bytes for the count (u64)
+        + 32;  // 32 bytes for the review key
+}
+
+impl MovieAggregate {
+    pub const SIZE: usize = DISCRIMINATOR_LEN // 8 byte type tag
+        + 1  // 1 byte for is_initialized (boolean)
+        + 8  // 8 bytes for the review count (u64)
+        + 8  // 8 bytes for the rating sum (u64)
+        + 5 * 8;  // 8 bytes for each star in the histogram (u64)
+
+    // `rating` has already been checked to be within 1..=5
+    pub fn add_rating(&mut self, rating: u8) {
+        self.review_count += 1;
+        self.rating_sum += rating as u64;
+        self.histogram[rating as usize - 1] += 1;
+    }
+
+    // Saturating, so an aggregate that missed a review can't underflow
+    pub fn remove_rating(&mut self, rating: u8) {
+        self.review_count = self.review_count.saturating_sub(1);
+        self.rating_sum = self.rating_sum.saturating_sub(rating as u64);
+        self.histogram[rating as usize - 1] = self.histogram[rating as usize - 1].saturating_sub(1);
+    }
+
+    pub fn average_rating(&self) -> Option<f64> {
+        if self.review_count == 0 {
+            return None;
+        }
+
+        Some(self.rating_sum as f64 / self.review_count as f64)
+    }
+}
+
+pub const DISCRIMINATOR_LEN: usize = 8;
+
+// Implemented by every account type the program owns. The tag is the first
+// 8 bytes of sha256("account:<TypeName>") and sits at offset 0 of the account,
+// so clients can find every account of a type with a memcmp filter
+pub trait ProgramAccount: BorshSerialize + BorshDeserialize {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN];
+
+    // Deserializes an account, failing unless it carries this type's tag
+    fn load(data: &[u8]) -> Result<Self, ProgramError> {
+        if !data.starts_with(&Self::DISCRIMINATOR) {
+            return Err(ReviewError::InvalidAccountKind.into());
+        }
+
+        try_from_slice_unchecked::<Self>(data).map_err(|_| ProgramError::InvalidAccountData)
+    }
+}
+
+impl ProgramAccount for MovieAccountState {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x67, 0x92, 0x20, 0xd4, 0xbb, 0xa6, 0x28, 0x0d];
+}
+
+impl ProgramAccount for MovieComment {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x23, 0x96, 0xe2, 0x86, 0xac, 0x2e, 0xb0, 0xc9];
+}
+
+impl ProgramAccount for MovieCommentCounter {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x62, 0x0a, 0x12, 0xa2, 0x18, 0x68, 0x60, 0xac];
+}
+
+impl ProgramAccount for MovieAggregate {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0xf5, 0xa3, 0x25, 0xb9, 0xa2, 0x1e, 0xb6, 0xcb];
+}
+
+impl ProgramAccount for Movie {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0xde, 0x60, 0x91, 0xc7, 0xed, 0xc4, 0xcd, 0xb4];
+}
+
+impl ProgramAccount for MovieRegistry {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x92, 0xa8, 0x22, 0x81, 0x52, 0xeb, 0x64, 0x9a];
+}
+
+impl ProgramAccount for MovieTitleIndex {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x1c, 0x6a, 0x5e, 0xa2, 0xad, 0x2e, 0xf0, 0x9b];
+}
+
+impl ProgramAccount for MovieReplyCounter {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x40, 0xc6, 0x16, 0xa2, 0x31, 0xab, 0x8b, 0x98];
+}
+
+impl ProgramAccount for MovieVote {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x77, 0xe3, 0xb7, 0xd4, 0xdd, 0x0b, 0x47, 0x42];
+}
+
+impl ProgramAccount for MovieVoteTally {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x82, 0x96, 0x6f, 0x12, 0xe5, 0xf8, 0x39, 0x4d];
+}
+
+impl ProgramAccount for Config {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x9b, 0x0c, 0xaa, 0xe0, 0x1e, 0xfa, 0xcc, 0x82];
+}
+
+impl ProgramAccount for RewardLedger {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x7a, 0x43, 0x24, 0x0f, 0x03, 0xbf, 0x33, 0xeb];
+}
+
+impl ProgramAccount for ReviewerState {
+    const DISCRIMINATOR: [u8; DISCRIMINATOR_LEN] = [0x56, 0x7e, 0x96, 0xe5, 0xa1, 0x94, 0xf3, 0xdf];
+}
+
+// Tells the program's account types apart by their tag, for indexers and
+// anything else handed an account of unknown type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AccountKind {
+    Review,
+    Comment,
+    Counter,
+    Aggregate,
+    Movie,
+    Registry,
+    TitleIndex,
+    ReplyCounter,
+    Vote,
+    VoteTally,
+    Config,
+    RewardLedger,
+    Reviewer,
+}
+
+impl AccountKind {
+    pub fn discriminator(self) -> [u8; DISCRIMINATOR_LEN] {
+        match self {
+            AccountKind::Review => MovieAccountState::DISCRIMINATOR,
+            AccountKind::Comment => MovieComment::DISCRIMINATOR,
+            AccountKind::Counter => MovieCommentCounter::DISCRIMINATOR,
+            AccountKind::Aggregate => MovieAggregate::DISCRIMINATOR,
+            AccountKind::Movie => Movie::DISCRIMINATOR,
+            AccountKind::Registry => MovieRegistry::DISCRIMINATOR,
+            AccountKind::TitleIndex => MovieTitleIndex::DISCRIMINATOR,
+            AccountKind::ReplyCounter => MovieReplyCounter::DISCRIMINATOR,
+            AccountKind::Vote => MovieVote::DISCRIMINATOR,
+            AccountKind::VoteTally => MovieVoteTally::DISCRIMINATOR,
+            AccountKind::Config => Config::DISCRIMINATOR,
+            AccountKind::RewardLedger => RewardLedger::DISCRIMINATOR,
+            AccountKind::Reviewer => ReviewerState::DISCRIMINATOR,
+        }
+    }
+
+    // Reads the tag at the start of an account's data
+    pub fn from_account_data(data: &[u8]) -> Result<Self, ProgramError> {
+        let tag = data.get(..DISCRIMINATOR_LEN).ok_or(ReviewError::InvalidAccountKind)?;
+
+        [
+            AccountKind::Review,
+            AccountKind::Comment,
+            AccountKind::Counter,
+            AccountKind::Aggregate,
+            AccountKind::Movie,
+            AccountKind::Registry,
+            AccountKind::TitleIndex,
+            AccountKind::ReplyCounter,
+            AccountKind::Vote,
+            AccountKind::VoteTally,
+            AccountKind::Config,
+            AccountKind::RewardLedger,
+            AccountKind::Reviewer,
+        ]
+            .into_iter()
+            .find(|kind| kind.discriminator() == tag)
+            .ok_or_else(|| ReviewError::InvalidAccountKind.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn len<T: BorshSerialize>(account: &T) -> usize {
+        account.try_to_vec().unwrap().len()
+    }
+
+    #[test]
+    fn review_sizes_match_serialized_length() {
+        let review = MovieAccountState {
+            discriminator: MovieAccountState::DISCRIMINATOR,
+            is_initialized: true,
+            reviewer: Pubkey::new_unique(),
+            rating: 4,
+            movie_id: 7,
+            description: "A slow burn".to_string(),
+            comment_anchor: Pubkey::new_unique(),
+        };
+        assert_eq!(len(&review), MovieAccountState::get_account_size(&review.description));
+    }
+
+    #[test]
+    fn every_account_kind_has_its_own_tag() {
+        let kinds = [
+            AccountKind::Review,
+            AccountKind::Comment,
+            AccountKind::Counter,
+            AccountKind::Aggregate,
+            AccountKind::Movie,
+            AccountKind::Registry,
+            AccountKind::TitleIndex,
+            AccountKind::ReplyCounter,
+            AccountKind::Vote,
+            AccountKind::VoteTally,
+            AccountKind::Config,
+            AccountKind::RewardLedger,
+            AccountKind::Reviewer,
+        ];
+
+        for kind in kinds {
+            assert_eq!(AccountKind::from_account_data(&kind.discriminator()).unwrap(), kind);
+        }
+    }
+
+    // A review as the first deployed program left it, in a 1000 byte account
+    fn legacy_review_data(discriminator: &str) -> Vec<u8> {
+        let review = LegacyMovieAccountState {
+            discriminator: discriminator.to_string(),
+            is_initialized: true,
+            reviewer: Pubkey::new_unique(),
+            rating: 4,
+            title: "Heat".to_string(),
+            description: "A slow burn".to_string(),
+        };
+        let mut data = review.try_to_vec().unwrap();
+        data.resize(1000, 0);
+        data
+    }
+
+    #[test]
+    fn legacy_review_loads_the_first_layout() {
+        let review = LegacyMovieAccountState::load(&legacy_review_data("")).unwrap();
+        assert!(review.is_initialized);
+        assert_eq!((review.rating, review.title.as_str()), (4, "Heat"));
+        assert_eq!(review.description, "A slow burn");
+
+        assert!(LegacyMovieAccountState::load(&legacy_review_data(LegacyMovieAccountState::DISCRIMINATOR)).is_ok());
+        assert_eq!(
+            LegacyMovieAccountState::load(&legacy_review_data("comment")).err(),
+            Some(ReviewError::InvalidAccountKind.into())
+        );
+
+        // Neither layout reads as the other
+        assert!(MovieAccountState::load(&legacy_review_data("")).is_err());
+        let review = MovieAccountState {
+            discriminator: MovieAccountState::DISCRIMINATOR,
+            is_initialized: true,
+            reviewer: Pubkey::new_unique(),
+            rating: 4,
+            movie_id: 7,
+            description: "A slow burn".to_string(),
+            comment_anchor: Pubkey::new_unique(),
+        };
+        assert!(LegacyMovieAccountState::load(&review.try_to_vec().unwrap()).is_err());
+    }
+
+    #[test]
+    fn legacy_counter_reads_the_count_from_a_zeroed_account() {
+        let mut data = vec![0; 20];
+        assert_eq!(LegacyMovieCommentCounter::load(&data).unwrap().counter, 0);
+
+        data[5..13].copy_from_slice(&3u64.to_le_bytes());
+        let counter = LegacyMovieCommentCounter::load(&data).unwrap();
+        assert!(!counter.is_initialized);
+        assert_eq!(counter.counter, 3);
+
+        assert!(LegacyMovieCommentCounter::load(&data[..12]).is_err());
+    }
+
+    #[test]
+    fn comment_sizes_match_serialized_length() {
+        let mut comment = MovieComment {
+            discriminator: MovieComment::DISCRIMINATOR,
+            is_initialized: true,
+            reviewer: Pubkey::new_unique(),
+            commenter: Pubkey::new_unique(),
+            comment: "Agreed".to_string(),
+            count: 3,
+            edit_count: 1,
+            last_edited_slot: 42,
+            is_deleted: false,
+            parent_comment: None,
+            depth: 0,
+        };
+        assert_eq!(len(&comment), MovieComment::get_account_size(&comment.comment, false));
+
+        comment.parent_comment = Some(Pubkey::new_unique());
+        comment.depth = 1;
+        assert_eq!(len(&comment), MovieComment::get_account_size(&comment.comment, true));
+    }
+
+    #[test]
+    fn movie_sizes_match_serialized_length() {
+        let movie = Movie {
+            discriminator: Movie::DISCRIMINATOR,
+            is_initialized: true,
+            id: 7,
+            title: "Heat".to_string(),
+            year: 1995,
+            imdb_id: "tt0113277".to_string(),
+        };
+        assert_eq!(len(&movie), Movie::get_account_size(&movie.title, &movie.imdb_id));
+
+        let registry = MovieRegistry {
+            discriminator: MovieRegistry::DISCRIMINATOR,
+            is_initialized: true,
+            movie_count: 8,
+        };
+        assert_eq!(len(&registry), MovieRegistry::SIZE);
+
+        let index = MovieTitleIndex {
+            discriminator: MovieTitleIndex::DISCRIMINATOR,
+            is_initialized: true,
+            movie_id: 7,
+        };
+        assert_eq!(len(&index), MovieTitleIndex::SIZE);
+
+        let aggregate = MovieAggregate {
+            discriminator: MovieAggregate::DISCRIMINATOR,
+            is_initialized: true,
+            review_count: 2,
+            rating_sum: 9,
+            histogram: [0, 0, 0, 1, 1],
+        };
+        assert_eq!(len(&aggregate), MovieAggregate::SIZE);
+    }
+
+    #[test]
+    fn counter_and_vote_sizes_match_serialized_length() {
+        let counter = MovieCommentCounter {
+            discriminator: MovieCommentCounter::DISCRIMINATOR,
+            is_initialized: true,
+            counter: 3,
+            review: Pubkey::new_unique(),
+        };
+        assert_eq!(len(&counter), MovieCommentCounter::SIZE);
+
+        let reviewer = ReviewerState {
+            discriminator: ReviewerState::DISCRIMINATOR,
+            is_initialized: true,
+            review_count: 2,
+        };
+        assert_eq!(len(&reviewer), ReviewerState::SIZE);
+
+        let reply_counter = MovieReplyCounter {
+            discriminator: MovieReplyCounter::DISCRIMINATOR,
+            is_initialized: true,
+            counter: 3,
+        };
+        assert_eq!(len(&reply_counter), MovieReplyCounter::SIZE);
+
+        let vote = MovieVote {
+            discriminator: MovieVote::DISCRIMINATOR,
+            is_initialized: true,
+            voter: Pubkey::new_unique(),
+            review: Pubkey::new_unique(),
+            helpful: true,
+        };
+        assert_eq!(len(&vote), MovieVote::SIZE);
+
+        let tally = MovieVoteTally {
+            discriminator: MovieVoteTally::DISCRIMINATOR,
+            is_initialized: true,
+            helpful: 2,
+            unhelpful: 1,
+        };
+        assert_eq!(len(&tally), MovieVoteTally::SIZE);
+    }
+
+    #[test]
+    fn config_and_ledger_sizes_match_serialized_length() {
+        let reward = RewardSetting { amount: 10, enabled: true };
+        let limits = RewardLimits { cap_per_window: 50, window_slots: 100, cooldown_slots: 5 };
+        assert_eq!(len(&reward), RewardSetting::SIZE);
+        assert_eq!(len(&limits), RewardLimits::SIZE);
+
+        // The config is allocated for its largest policy
+        let policy = SelfCommentPolicy::ReducedReward { amount: 1 };
+        assert_eq!(len(&policy), SelfCommentPolicy::MAX_SIZE);
+        assert!(len(&SelfCommentPolicy::NoReward) <= SelfCommentPolicy::MAX_SIZE);
+        assert!(len(&SelfCommentPolicy::Reject) <= SelfCommentPolicy::MAX_SIZE);
+
+        let config = Config {
+            discriminator: Config::DISCRIMINATOR,
+            is_initialized: true,
+            admin: Pubkey::new_unique(),
+            review_reward: reward,
+            comment_reward: reward,
+            reward_limits: limits,
+            self_comment_policy: policy,
+        };
+        assert_eq!(len(&config), Config::SIZE);
+
+        let ledger = RewardLedger {
+            discriminator: RewardLedger::DISCRIMINATOR,
+            is_initialized: true,
+            user: Pubkey::new_unique(),
+            window_start: 100,
+            window_minted: 10,
+            last_reward_slot: 120,
+        };
+        assert_eq!(len(&ledger), RewardLedger::SIZE);
+    }
+
+    fn ledger() -> RewardLedger {
+        RewardLedger {
+            discriminator: RewardLedger::DISCRIMINATOR,
+            is_initialized: true,
+            user: Pubkey::new_unique(),
+            window_start: 0,
+            window_minted: 0,
+            last_reward_slot: 0,
+        }
+    }
+
+    fn limits(cap_per_window: u64, window_slots: u64, cooldown_slots: u64) -> RewardLimits {
+        RewardLimits { cap_per_window, window_slots, cooldown_slots }
+    }
+
+    #[test]
+    fn grant_without_limits_pays_everything() {
+        let mut ledger = ledger();
+        let limits = limits(0, 0, 0);
+
+        assert_eq!(ledger.grant(&limits, 10, 5), 5);
+        assert_eq!(ledger.grant(&limits, 10, 5), 5);
+        assert_eq!(ledger.grant(&limits, 11, u64::MAX), u64::MAX);
+        assert_eq!((ledger.window_minted, ledger.last_reward_slot), (u64::MAX, 11));
+    }
+
+    #[test]
+    fn grant_of_nothing_leaves_the_ledger_alone() {
+        let mut ledger = ledger();
+
+        assert_eq!(ledger.grant(&limits(10, 100, 5), 150, 0), 0);
+        assert_eq!((ledger.window_minted, ledger.last_reward_slot), (0, 0));
+        // Only the window moved
+        assert_eq!(ledger.window_start, 100);
+    }
+
+    #[test]
+    fn grant_pays_part_of_a_reward_that_reaches_the_cap() {
+        let mut ledger = ledger();
+        let limits = limits(10, 100, 0);
+
+        assert_eq!(ledger.grant(&limits, 100, 6), 6);
+        // Only 4 left in the window
+        assert_eq!(ledger.grant(&limits, 101, 6), 4);
+        assert_eq!((ledger.window_minted, ledger.last_reward_slot), (10, 101));
+
+        // Exhausted, nothing more and the last reward slot stays put
+        assert_eq!(ledger.grant(&limits, 150, 6), 0);
+        assert_eq!((ledger.window_minted, ledger.last_reward_slot), (10, 101));
+    }
+
+    #[test]
+    fn grant_resets_the_cap_in_the_next_window() {
+        let mut ledger = ledger();
+        let limits = limits(10, 100, 0);
+
+        assert_eq!(ledger.grant(&limits, 150, 10), 10);
+        assert_eq!(ledger.window_start, 100);
+        assert_eq!(ledger.grant(&limits, 199, 10), 0);
+
+        // The window starts at the multiple of its length the slot falls in,
+        // not at the slot of the reward that opened it
+        assert_eq!(ledger.grant(&limits, 200, 3), 3);
+        assert_eq!((ledger.window_start, ledger.window_minted), (200, 3));
+
+        // Skipping several windows lands on the current one
+        assert_eq!(ledger.grant(&limits, 1_234, 10), 10);
+        assert_eq!((ledger.window_start, ledger.window_minted), (1_200, 10));
+    }
+
+    #[test]
+    fn grant_with_no_window_caps_for_life() {
+        let mut ledger = ledger();
+        let limits = limits(10, 0, 0);
+
+        assert_eq!(ledger.grant(&limits, 100, 7), 7);
+        assert_eq!(ledger.grant(&limits, 1_000_000, 7), 3);
+        assert_eq!(ledger.grant(&limits, u64::MAX, 7), 0);
+        assert_eq!((ledger.window_start, ledger.window_minted), (0, 10));
+    }
+
+    #[test]
+    fn grant_waits_out_the_cooldown() {
+        let mut ledger = ledger();
+        let limits = limits(0, 0, 5);
+
+        // No cooldown before the first reward, even this early on
+        assert_eq!(ledger.grant(&limits, 1, 2), 2);
+        assert_eq!(ledger.grant(&limits, 5, 2), 0);
+        assert_eq!((ledger.window_minted, ledger.last_reward_slot), (2, 1));
+
+        // Over once cooldown_slots have passed since the last reward
+        assert_eq!(ledger.grant(&limits, 6, 2), 2);
+        assert_eq!(ledger.last_reward_slot, 6);
+    }
+
+    #[test]
+    fn grant_cooldown_holds_across_a_window_reset() {
+        let mut ledger = ledger();
+        let limits = limits(10, 100, 20);
+
+        assert_eq!(ledger.grant(&limits, 95, 10), 10);
+
+        // A new window, but still cooling down. The window is reset anyway,
+        // so the full cap is there once the cooldown ends
+        assert_eq!(ledger.grant(&limits, 105, 10), 0);
+        assert_eq!((ledger.window_start, ledger.window_minted, ledger.last_reward_slot), (100, 0, 95));
+
+        assert_eq!(ledger.grant(&limits, 115, 10), 10);
+        assert_eq!((ledger.window_start, ledger.window_minted, ledger.last_reward_slot), (100, 10, 115));
+    }
 }
@@ -0,0 +1,569 @@
+use std::ops::Deref;
+
+use solana_program::{
+    account_info::{next_account_info, AccountInfo},
+    bpf_loader_upgradeable,
+    entrypoint::ProgramResult,
+    msg,
+    program_error::ProgramError,
+    program_pack::IsInitialized,
+    pubkey::Pubkey,
+    system_program::ID as SYSTEM_PROGRAM_ID,
+    sysvar::rent::ID as RENT_PROGRAM_ID,
+};
+use spl_associated_token_account::{
+    get_associated_token_address, ID as ASSOCIATED_TOKEN_PROGRAM_ID,
+};
+use spl_token::ID as TOKEN_PROGRAM_ID;
+
+use crate::error::ReviewError;
+use crate::metadata::TOKEN_METADATA_PROGRAM_ID;
+use crate::pda::{
+    find_config_address, find_mint_address, find_mint_authority_address, find_program_data_address,
+    find_reward_ledger_address,
+};
+use crate::state::{Config, ProgramAccount};
+
+// Typed wrappers for the accounts an instruction is handed. Each constructor
+// runs one kind of check and the wrapper derefs to the AccountInfo, so a
+// processor declares its accounts with these and every instruction gets the
+// same guarantees for the same kind of account.
+
+// Signed the transaction
+pub struct Signer<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> Signer<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        if !info.is_signer {
+            msg!("Missing required signature");
+            return Err(ProgramError::MissingRequiredSignature);
+        }
+
+        Ok(Self { info })
+    }
+}
+
+// Owned by this program and holding an initialized T, checked by its tag
+pub struct ProgramOwned<'a, 'info, T> {
+    info: &'a AccountInfo<'info>,
+    pub data: T,
+}
+
+impl<'a, 'info, T: ProgramAccount + IsInitialized> ProgramOwned<'a, 'info, T> {
+    pub fn new(info: &'a AccountInfo<'info>, program_id: &Pubkey) -> Result<Self, ProgramError> {
+        check_program_owned(info, program_id)?;
+
+        let data = T::load(&info.data.borrow())?;
+        if !data.is_initialized() {
+            msg!("Account {} is not initialized", info.key);
+            return Err(ReviewError::UninitializedAccount.into());
+        }
+
+        Ok(Self { info, data })
+    }
+}
+
+// The owner check on its own, for accounts whose contents can't be loaded
+pub fn check_program_owned(info: &AccountInfo, program_id: &Pubkey) -> ProgramResult {
+    if info.owner != program_id {
+        msg!("Account {} is not owned by the program", info.key);
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    Ok(())
+}
+
+// Lives at the address the program derives for it, `expected` is the result
+// of the matching find_*_address helper in pda.rs
+pub struct Pda<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+    pub bump: u8,
+}
+
+impl<'a, 'info> Pda<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>, expected: (Pubkey, u8)) -> Result<Self, ProgramError> {
+        let (address, bump) = expected;
+        if *info.key != address {
+            msg!("Invalid seeds for PDA {}", info.key);
+            return Err(ReviewError::InvalidPDA.into());
+        }
+
+        Ok(Self { info, bump })
+    }
+}
+
+// The associated token account `wallet` holds for `mint`
+pub struct AssociatedTokenAccount<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> AssociatedTokenAccount<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>, wallet: &Pubkey, mint: &Pubkey) -> Result<Self, ProgramError> {
+        if *info.key != get_associated_token_address(wallet, mint) {
+            msg!("Incorrect associated token account");
+            return Err(ReviewError::IncorrectAccountError.into());
+        }
+
+        Ok(Self { info })
+    }
+}
+
+// Accounts that must be one specific program or sysvar
+pub struct SystemProgram<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> SystemProgram<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        check_address(info, &SYSTEM_PROGRAM_ID, "system program")?;
+        Ok(Self { info })
+    }
+}
+
+pub struct TokenProgram<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TokenProgram<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        check_address(info, &TOKEN_PROGRAM_ID, "token program")?;
+        Ok(Self { info })
+    }
+}
+
+pub struct AssociatedTokenProgram<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> AssociatedTokenProgram<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        check_address(info, &ASSOCIATED_TOKEN_PROGRAM_ID, "associated token program")?;
+        Ok(Self { info })
+    }
+}
+
+pub struct TokenMetadataProgram<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> TokenMetadataProgram<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        check_address(info, &TOKEN_METADATA_PROGRAM_ID, "token metadata program")?;
+        Ok(Self { info })
+    }
+}
+
+pub struct RentSysvar<'a, 'info> {
+    info: &'a AccountInfo<'info>,
+}
+
+impl<'a, 'info> RentSysvar<'a, 'info> {
+    pub fn new(info: &'a AccountInfo<'info>) -> Result<Self, ProgramError> {
+        check_address(info, &RENT_PROGRAM_ID, "rent sysvar")?;
+        Ok(Self { info })
+    }
+}
+
+fn check_address(info: &AccountInfo, expected: &Pubkey, name: &str) -> ProgramResult {
+    if info.key != expected {
+        msg!("Incorrect {}", name);
+        return Err(ReviewError::IncorrectAccountError.into());
+    }
+
+    Ok(())
+}
+
+// The accounts needed to mint reward tokens to `recipient`, in the order
+// every rewarding instruction lists them: the recipient's reward ledger and
+// associated token account, which may not exist yet, and the program that
+// creates the latter. The config that sets the amounts isn't part of the
+// group, rewarding instructions always take it
+pub struct RewardAccounts<'a, 'info> {
+    pub token_mint: Pda<'a, 'info>,
+    pub mint_auth: Pda<'a, 'info>,
+    pub user_ata: AssociatedTokenAccount<'a, 'info>,
+    pub token_program: TokenProgram<'a, 'info>,
+    pub ledger: Pda<'a, 'info>,
+    pub associated_token_program: AssociatedTokenProgram<'a, 'info>,
+}
+
+impl<'a, 'info> RewardAccounts<'a, 'info> {
+    // The group is optional and always last, so None means the instruction
+    // ended before it. A group that is only partly there is still an error
+    pub fn optional<I>(program_id: &Pubkey, recipient: &Pubkey, iter: &mut I) -> Result<Option<Self>, ProgramError>
+    where
+        I: Iterator<Item = &'a AccountInfo<'info>>,
+    {
+        let token_mint = match iter.next() {
+            Some(info) => Pda::new(info, find_mint_address(program_id))?,
+            None => return Ok(None),
+        };
+        let mint_auth = Pda::new(next_account_info(iter)?, find_mint_authority_address(program_id))?;
+        let user_ata = AssociatedTokenAccount::new(next_account_info(iter)?, recipient, token_mint.key)?;
+        let token_program = TokenProgram::new(next_account_info(iter)?)?;
+        let ledger = Pda::new(next_account_info(iter)?, find_reward_ledger_address(program_id, recipient))?;
+        let associated_token_program = AssociatedTokenProgram::new(next_account_info(iter)?)?;
+
+        Ok(Some(Self {
+            token_mint,
+            mint_auth,
+            user_ata,
+            token_program,
+            ledger,
+            associated_token_program,
+        }))
+    }
+}
+
+// The program's config, checked to live at its PDA
+pub fn load_config(program_id: &Pubkey, info: &AccountInfo) -> Result<Config, ProgramError> {
+    let config = ProgramOwned::<Config>::new(info, program_id)?.data;
+    Pda::new(info, find_config_address(program_id))?;
+
+    Ok(config)
+}
+
+// The ProgramData header is bincode: a u32 variant tag, the slot the program
+// was last deployed in, then the upgrade authority as an Option<Pubkey>
+const PROGRAM_DATA_TAG: u32 = 3;
+const PROGRAM_DATA_AUTHORITY_OFFSET: usize = 12;
+
+// The program's ProgramData account, checked to name `authority` as the
+// upgrade authority. A program that has been made immutable has none
+pub fn check_upgrade_authority(program_id: &Pubkey, info: &AccountInfo, authority: &Pubkey) -> ProgramResult {
+    Pda::new(info, find_program_data_address(program_id))?;
+    if info.owner != &bpf_loader_upgradeable::id() {
+        msg!("Program data is not owned by the upgradeable loader");
+        return Err(ProgramError::IllegalOwner);
+    }
+
+    let data = info.data.borrow();
+    let header = data.get(..PROGRAM_DATA_AUTHORITY_OFFSET + 1 + 32).ok_or(ProgramError::InvalidAccountData)?;
+    if header[..4] != PROGRAM_DATA_TAG.to_le_bytes() {
+        msg!("Account {} is not program data", info.key);
+        return Err(ProgramError::InvalidAccountData);
+    }
+
+    let upgrade_authority = &header[PROGRAM_DATA_AUTHORITY_OFFSET..];
+    if upgrade_authority[0] != 1 || upgrade_authority[1..] != authority.to_bytes() {
+        msg!("{} is not the program's upgrade authority", authority);
+        return Err(ReviewError::NotUpgradeAuthority.into());
+    }
+
+    Ok(())
+}
+
+macro_rules! deref_to_account_info {
+    ($($wrapper:ident),* $(,)?) => {
+        $(
+            impl<'a, 'info> Deref for $wrapper<'a, 'info> {
+                type Target = AccountInfo<'info>;
+
+                fn deref(&self) -> &Self::Target {
+                    self.info
+                }
+            }
+        )*
+    };
+}
+
+deref_to_account_info!(
+    Signer,
+    Pda,
+    AssociatedTokenAccount,
+    SystemProgram,
+    TokenProgram,
+    AssociatedTokenProgram,
+    TokenMetadataProgram,
+    RentSysvar,
+);
+
+impl<'a, 'info, T> Deref for ProgramOwned<'a, 'info, T> {
+    type Target = AccountInfo<'info>;
+
+    fn deref(&self) -> &Self::Target {
+        self.info
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use borsh::BorshSerialize;
+    use solana_program::clock::Epoch;
+
+    use crate::state::{MovieVoteTally, RewardLimits, RewardSetting, SelfCommentPolicy};
+
+    struct TestAccount {
+        key: Pubkey,
+        owner: Pubkey,
+        lamports: u64,
+        data: Vec<u8>,
+        is_signer: bool,
+    }
+
+    impl TestAccount {
+        fn new(key: Pubkey) -> Self {
+            TestAccount {
+                key,
+                owner: SYSTEM_PROGRAM_ID,
+                lamports: 1_000_000,
+                data: vec![],
+                is_signer: false,
+            }
+        }
+
+        fn owned_by(mut self, owner: Pubkey, data: Vec<u8>) -> Self {
+            self.owner = owner;
+            self.data = data;
+            self
+        }
+
+        fn info(&mut self) -> AccountInfo<'_> {
+            AccountInfo::new(
+                &self.key,
+                self.is_signer,
+                true,
+                &mut self.lamports,
+                &mut self.data,
+                &self.owner,
+                false,
+                Epoch::default(),
+            )
+        }
+    }
+
+    fn expect_err<T>(result: Result<T, ProgramError>) -> ProgramError {
+        match result {
+            Ok(_) => panic!("expected the check to fail"),
+            Err(err) => err,
+        }
+    }
+
+    fn config(is_initialized: bool) -> Config {
+        Config {
+            discriminator: Config::DISCRIMINATOR,
+            is_initialized,
+            admin: Pubkey::new_unique(),
+            review_reward: RewardSetting { amount: 10, enabled: true },
+            comment_reward: RewardSetting { amount: 5, enabled: true },
+            reward_limits: RewardLimits { cap_per_window: 0, window_slots: 0, cooldown_slots: 0 },
+            self_comment_policy: SelfCommentPolicy::NoReward,
+        }
+    }
+
+    fn config_account(program_id: &Pubkey, config: Config) -> TestAccount {
+        let (address, _) = find_config_address(program_id);
+        TestAccount::new(address).owned_by(*program_id, config.try_to_vec().unwrap())
+    }
+
+    #[test]
+    fn signer_requires_a_signature() {
+        let mut account = TestAccount::new(Pubkey::new_unique());
+        assert_eq!(expect_err(Signer::new(&account.info())), ProgramError::MissingRequiredSignature);
+
+        account.is_signer = true;
+        assert!(Signer::new(&account.info()).is_ok());
+    }
+
+    #[test]
+    fn program_owned_checks_owner_tag_layout_and_initialization() {
+        let program_id = Pubkey::new_unique();
+
+        let mut account = config_account(&program_id, config(true));
+        assert!(ProgramOwned::<Config>::new(&account.info(), &program_id).is_ok());
+
+        account.owner = Pubkey::new_unique();
+        assert_eq!(
+            expect_err(ProgramOwned::<Config>::new(&account.info(), &program_id)),
+            ProgramError::IllegalOwner
+        );
+
+        // Another account type's tag
+        let tally = MovieVoteTally {
+            discriminator: MovieVoteTally::DISCRIMINATOR,
+            is_initialized: true,
+            helpful: 0,
+            unhelpful: 0,
+        };
+        let mut account = TestAccount::new(Pubkey::new_unique()).owned_by(program_id, tally.try_to_vec().unwrap());
+        assert_eq!(
+            expect_err(ProgramOwned::<Config>::new(&account.info(), &program_id)),
+            ReviewError::InvalidAccountKind.into()
+        );
+
+        // The right tag on data too short to hold the rest
+        let mut account = TestAccount::new(Pubkey::new_unique()).owned_by(program_id, Config::DISCRIMINATOR.to_vec());
+        assert_eq!(
+            expect_err(ProgramOwned::<Config>::new(&account.info(), &program_id)),
+            ProgramError::InvalidAccountData
+        );
+
+        let mut account = config_account(&program_id, config(false));
+        assert_eq!(
+            expect_err(ProgramOwned::<Config>::new(&account.info(), &program_id)),
+            ReviewError::UninitializedAccount.into()
+        );
+    }
+
+    #[test]
+    fn pda_requires_the_derived_address() {
+        let program_id = Pubkey::new_unique();
+        let expected = find_config_address(&program_id);
+
+        let mut account = TestAccount::new(Pubkey::new_unique());
+        assert_eq!(expect_err(Pda::new(&account.info(), expected)), ReviewError::InvalidPDA.into());
+
+        let mut account = TestAccount::new(expected.0);
+        assert_eq!(Pda::new(&account.info(), expected).unwrap().bump, expected.1);
+    }
+
+    #[test]
+    fn program_and_sysvar_wrappers_require_their_address() {
+        let incorrect = ReviewError::IncorrectAccountError.into();
+
+        let mut wrong = TestAccount::new(Pubkey::new_unique());
+        assert_eq!(expect_err(SystemProgram::new(&wrong.info())), incorrect);
+        assert_eq!(expect_err(TokenProgram::new(&wrong.info())), incorrect);
+        assert_eq!(expect_err(AssociatedTokenProgram::new(&wrong.info())), incorrect);
+        assert_eq!(expect_err(TokenMetadataProgram::new(&wrong.info())), incorrect);
+        assert_eq!(expect_err(RentSysvar::new(&wrong.info())), incorrect);
+
+        // Each one also refuses the others' addresses
+        let mut token_program = TestAccount::new(TOKEN_PROGRAM_ID);
+        assert_eq!(expect_err(SystemProgram::new(&token_program.info())), incorrect);
+        let mut system_program = TestAccount::new(SYSTEM_PROGRAM_ID);
+        assert_eq!(expect_err(TokenProgram::new(&system_program.info())), incorrect);
+        assert_eq!(expect_err(RentSysvar::new(&system_program.info())), incorrect);
+
+        assert!(SystemProgram::new(&TestAccount::new(SYSTEM_PROGRAM_ID).info()).is_ok());
+        assert!(TokenProgram::new(&TestAccount::new(TOKEN_PROGRAM_ID).info()).is_ok());
+        assert!(AssociatedTokenProgram::new(&TestAccount::new(ASSOCIATED_TOKEN_PROGRAM_ID).info()).is_ok());
+        assert!(TokenMetadataProgram::new(&TestAccount::new(TOKEN_METADATA_PROGRAM_ID).info()).is_ok());
+        assert!(RentSysvar::new(&TestAccount::new(RENT_PROGRAM_ID).info()).is_ok());
+    }
+
+    #[test]
+    fn associated_token_account_requires_wallet_and_mint() {
+        let wallet = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let incorrect = ReviewError::IncorrectAccountError.into();
+
+        let mut account = TestAccount::new(get_associated_token_address(&wallet, &mint));
+        assert!(AssociatedTokenAccount::new(&account.info(), &wallet, &mint).is_ok());
+        assert_eq!(
+            expect_err(AssociatedTokenAccount::new(&account.info(), &Pubkey::new_unique(), &mint)),
+            incorrect
+        );
+        assert_eq!(
+            expect_err(AssociatedTokenAccount::new(&account.info(), &wallet, &Pubkey::new_unique())),
+            incorrect
+        );
+
+        let mut account = TestAccount::new(Pubkey::new_unique());
+        assert_eq!(expect_err(AssociatedTokenAccount::new(&account.info(), &wallet, &mint)), incorrect);
+    }
+
+    #[test]
+    fn load_config_requires_an_owned_config_at_its_pda() {
+        let program_id = Pubkey::new_unique();
+        let expected = config(true);
+
+        let mut account = config_account(&program_id, config(true));
+        let loaded = load_config(&program_id, &account.info()).unwrap();
+        assert_eq!(loaded.review_reward, expected.review_reward);
+        assert_eq!(loaded.self_comment_policy, expected.self_comment_policy);
+
+        // A valid config anywhere but the config PDA
+        let mut account = TestAccount::new(Pubkey::new_unique())
+            .owned_by(program_id, config(true).try_to_vec().unwrap());
+        assert_eq!(expect_err(load_config(&program_id, &account.info())), ReviewError::InvalidPDA.into());
+
+        let mut account = config_account(&program_id, config(true));
+        account.owner = Pubkey::new_unique();
+        assert_eq!(expect_err(load_config(&program_id, &account.info())), ProgramError::IllegalOwner);
+
+        let mut account = config_account(&program_id, config(false));
+        assert_eq!(
+            expect_err(load_config(&program_id, &account.info())),
+            ReviewError::UninitializedAccount.into()
+        );
+
+        let mut account = TestAccount::new(find_config_address(&program_id).0);
+        assert_eq!(expect_err(load_config(&program_id, &account.info())), ProgramError::IllegalOwner);
+    }
+
+    #[test]
+    fn upgrade_authority_is_read_from_the_program_data() {
+        let program_id = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let program_data = |tag: u32, upgrade_authority: Option<Pubkey>| {
+            let mut data = tag.to_le_bytes().to_vec();
+            data.extend(0u64.to_le_bytes());
+            data.extend(upgrade_authority.try_to_vec().unwrap());
+            data.resize(PROGRAM_DATA_AUTHORITY_OFFSET + 1 + 32, 0);
+            TestAccount::new(find_program_data_address(&program_id).0)
+                .owned_by(bpf_loader_upgradeable::id(), data)
+        };
+
+        let mut account = program_data(PROGRAM_DATA_TAG, Some(authority));
+        assert!(check_upgrade_authority(&program_id, &account.info(), &authority).is_ok());
+        assert_eq!(
+            expect_err(check_upgrade_authority(&program_id, &account.info(), &Pubkey::new_unique())),
+            ReviewError::NotUpgradeAuthority.into()
+        );
+
+        account.owner = program_id;
+        assert_eq!(
+            expect_err(check_upgrade_authority(&program_id, &account.info(), &authority)),
+            ProgramError::IllegalOwner
+        );
+
+        // Another program's ProgramData
+        let mut account = program_data(PROGRAM_DATA_TAG, Some(authority));
+        account.key = find_program_data_address(&Pubkey::new_unique()).0;
+        assert_eq!(
+            expect_err(check_upgrade_authority(&program_id, &account.info(), &authority)),
+            ReviewError::InvalidPDA.into()
+        );
+
+        // A loader account that isn't ProgramData, e.g. a Buffer
+        let mut account = program_data(1, Some(authority));
+        assert_eq!(
+            expect_err(check_upgrade_authority(&program_id, &account.info(), &authority)),
+            ProgramError::InvalidAccountData
+        );
+
+        let mut account = program_data(PROGRAM_DATA_TAG, None);
+        assert_eq!(
+            expect_err(check_upgrade_authority(&program_id, &account.info(), &authority)),
+            ReviewError::NotUpgradeAuthority.into()
+        );
+    }
+
+    #[test]
+    fn reward_accounts_are_all_or_nothing() {
+        let program_id = Pubkey::new_unique();
+        let recipient = Pubkey::new_unique();
+
+        let none: Vec<AccountInfo> = vec![];
+        assert!(RewardAccounts::optional(&program_id, &recipient, &mut none.iter()).unwrap().is_none());
+
+        let mut token_mint = TestAccount::new(find_mint_address(&program_id).0);
+        let mut mint_auth = TestAccount::new(find_mint_authority_address(&program_id).0);
+        let partial = [token_mint.info(), mint_auth.info()];
+        assert_eq!(
+            expect_err(RewardAccounts::optional(&program_id, &recipient, &mut partial.iter())),
+            ProgramError::NotEnoughAccountKeys
+        );
+
+        let mut wrong_mint = TestAccount::new(Pubkey::new_unique());
+        let wrong = [wrong_mint.info()];
+        assert_eq!(
+            expect_err(RewardAccounts::optional(&program_id, &recipient, &mut wrong.iter())),
+            ReviewError::InvalidPDA.into()
+        );
+    }
+}